@@ -0,0 +1,88 @@
+//! Named test accounts derived from the well-known dev seeds.
+
+use sp_core::{sr25519, Pair as _};
+
+use crate::types::AccountId;
+
+/// Names of the dev accounts endowed at genesis.
+pub const DEV_ACCOUNTS: [&str; 6] = ["Alice", "Bob", "Charlie", "Dave", "Eve", "Ferdie"];
+
+/// A test account backed by an sr25519 keypair derived from `//<name>`.
+#[derive(Clone)]
+pub struct TestAccount {
+    name: String,
+    pair: sr25519::Pair,
+}
+
+impl TestAccount {
+    /// Derive the account from the dev seed `//<name>`.
+    pub fn new(name: &str) -> Self {
+        let pair =
+            sr25519::Pair::from_string(&format!("//{name}"), None).expect("Invalid dev seed");
+        Self {
+            name: name.to_string(),
+            pair,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn pair(&self) -> &sr25519::Pair {
+        &self.pair
+    }
+
+    pub fn public(&self) -> sr25519::Public {
+        self.pair.public()
+    }
+
+    pub fn account_id(&self) -> AccountId {
+        self.pair.public().into()
+    }
+}
+
+impl std::fmt::Debug for TestAccount {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("TestAccount")
+            .field("name", &self.name)
+            .field("account_id", &self.account_id())
+            .finish()
+    }
+}
+
+impl From<&TestAccount> for AccountId {
+    fn from(account: &TestAccount) -> Self {
+        account.account_id()
+    }
+}
+
+impl From<TestAccount> for AccountId {
+    fn from(account: TestAccount) -> Self {
+        account.account_id()
+    }
+}
+
+pub fn alice() -> TestAccount {
+    TestAccount::new("Alice")
+}
+
+pub fn bob() -> TestAccount {
+    TestAccount::new("Bob")
+}
+
+pub fn charlie() -> TestAccount {
+    TestAccount::new("Charlie")
+}
+
+pub fn dave() -> TestAccount {
+    TestAccount::new("Dave")
+}
+
+pub fn eve() -> TestAccount {
+    TestAccount::new("Eve")
+}
+
+pub fn ferdie() -> TestAccount {
+    TestAccount::new("Ferdie")
+}
@@ -0,0 +1,123 @@
+//! Several isolated clusters in one session.
+//!
+//! The clusters share the chain: block number, timestamp and balances. What belongs to a cluster
+//! is the `pallet_pink` state (system contract, key, owner, config), the `pallet_contracts` state
+//! with the contract storage and the event chain. Only one cluster lives in the sandbox at a
+//! time, the state of the others is kept in the session state and swapped in when a cluster is
+//! selected.
+
+use drink::session::Session;
+use frame_support::storage::unhashed;
+use sp_core::hashing::twox_128;
+
+use crate::event_chain;
+use crate::runtime::ClusterSetup;
+use crate::snapshot::ChainState;
+use crate::state;
+use crate::storage;
+use crate::types::{AccountId, ClusterId};
+use crate::{PinkRuntime, Result};
+
+/// Top-level storage prefixes of the items belonging to a cluster.
+fn prefixes() -> [Vec<u8>; 3] {
+    [
+        twox_128(b"Pink").to_vec(),
+        twox_128(b"Contracts").to_vec(),
+        event_chain::EVENT_BLOCKS_KEY.to_vec(),
+    ]
+}
+
+pub(crate) fn create(
+    session: &mut Session<PinkRuntime>,
+    cluster_id: ClusterId,
+    owner: AccountId,
+) -> Result<()> {
+    let exists = state::with_state(session, |state| {
+        state.active_cluster == cluster_id || state.idle_clusters.contains_key(&cluster_id)
+    });
+    if exists {
+        return Err(format!("ClusterAlreadyExists: {cluster_id:?}").into());
+    }
+    let setup = ClusterSetup { cluster_id, owner };
+    let mut genesis = PinkRuntime::with_cluster_setup(setup, Session::<PinkRuntime>::new)
+        .map_err(|err| format!("FailedToCreateCluster: {err:?}"))?;
+    let cluster = take(&mut genesis);
+    let accounts = genesis
+        .sandbox()
+        .execute_with(|| frame_system::Account::<PinkRuntime>::iter().collect::<Vec<_>>());
+    session.sandbox().execute_with(|| {
+        // The accounts of the new system contract, its deposits and the endowed owner.
+        for (account, info) in accounts {
+            if frame_system::Account::<PinkRuntime>::contains_key(&account) {
+                continue;
+            }
+            pallet_balances::TotalIssuance::<PinkRuntime>::mutate(|issuance| {
+                *issuance += info.data.free + info.data.reserved
+            });
+            frame_system::Account::<PinkRuntime>::insert(account, info);
+        }
+    });
+    state::with_state(session, |state| {
+        state.idle_clusters.insert(cluster_id, cluster)
+    });
+    Ok(())
+}
+
+/// Make `cluster_id` the cluster receiving the following calls.
+pub(crate) fn switch(session: &mut Session<PinkRuntime>, cluster_id: ClusterId) -> Result<()> {
+    let state = state::of(session);
+    let (previous, cluster) = {
+        let mut state = state.lock().expect("Session state poisoned");
+        if state.active_cluster == cluster_id {
+            return Ok(());
+        }
+        let cluster = state
+            .idle_clusters
+            .remove(&cluster_id)
+            .ok_or_else(|| format!("UnknownCluster: {cluster_id:?}"))?;
+        (state.active_cluster, cluster)
+    };
+    let current = take(session);
+    restore(session, &cluster);
+    let mut state = state.lock().expect("Session state poisoned");
+    state.idle_clusters.insert(previous, current);
+    state.active_cluster = cluster_id;
+    Ok(())
+}
+
+/// The state of the cluster in the sandbox.
+pub(crate) fn take(session: &mut Session<PinkRuntime>) -> ChainState {
+    session.sandbox().execute_with(|| ChainState {
+        storage: prefixes()
+            .iter()
+            .flat_map(|prefix| storage::top_pairs_with_prefix(prefix))
+            .collect(),
+        contracts: storage::contract_trie_ids()
+            .into_iter()
+            .map(|trie_id| {
+                let pairs = storage::child_pairs(&trie_id);
+                (trie_id, pairs)
+            })
+            .collect(),
+    })
+}
+
+/// Replace the cluster in the sandbox, leaving the shared chain state alone.
+pub(crate) fn restore(session: &mut Session<PinkRuntime>, cluster: &ChainState) {
+    session.sandbox().execute_with(|| {
+        for trie_id in storage::contract_trie_ids() {
+            storage::clear_child(&trie_id);
+        }
+        for prefix in prefixes() {
+            storage::clear_prefix(&prefix);
+        }
+        for (key, value) in &cluster.storage {
+            unhashed::put_raw(key, value);
+        }
+        for (trie_id, pairs) in &cluster.contracts {
+            for (key, value) in pairs {
+                storage::put_child(trie_id, key, value);
+            }
+        }
+    });
+}
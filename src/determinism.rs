@@ -0,0 +1,106 @@
+//! Detect transactions whose outcome depends on nondeterministic inputs.
+//!
+//! When the check is enabled on a session, each transaction submitted through
+//! [`Callable`](crate::Callable) is first executed twice on forked state: once with the inputs
+//! workers feed to transactions, and once with a different clock, randomness and HTTP responses.
+//! Any difference in the resulting storage, events or return data fails the transaction.
+
+use drink::session::Session;
+use frame_support::sp_io;
+use frame_support::sp_runtime::StateVersion;
+use scale::Encode;
+
+use crate::runtime::{ContractExecResult, System};
+use crate::state::{self, SeededRng, SharedState};
+use crate::types::ExecMode;
+use crate::{PinkRuntime, Result};
+
+/// Values returned by nondeterministic extension calls made in a transaction while checking.
+#[derive(Debug, Clone)]
+pub(crate) struct TxInputs {
+    pub clock: u64,
+    pub rng: SeededRng,
+    pub http_status: u16,
+}
+
+impl TxInputs {
+    fn perturbed() -> Self {
+        Self {
+            clock: 1_700_000_000_000,
+            rng: SeededRng::new([0x5a; 32]),
+            http_status: 200,
+        }
+    }
+}
+
+/// What a transaction left behind.
+#[derive(PartialEq, Eq)]
+struct Outcome {
+    storage_root: Vec<u8>,
+    events: Vec<Vec<u8>>,
+    output: Vec<u8>,
+}
+
+/// Run `call` twice on forked state with different nondeterministic inputs if the check is
+/// enabled for the session.
+pub(crate) fn check(
+    session: &mut Session<PinkRuntime>,
+    call: impl Fn() -> ContractExecResult,
+) -> Result<()> {
+    if !state::with_state(session, |state| state.determinism_check) {
+        return Ok(());
+    }
+    let state = state::of(session);
+    let baseline = run(session, &state, None, &call);
+    let perturbed = run(session, &state, Some(TxInputs::perturbed()), &call);
+    let mut diffs = vec![];
+    if baseline.storage_root != perturbed.storage_root {
+        diffs.push("storage");
+    }
+    if baseline.events != perturbed.events {
+        diffs.push("events");
+    }
+    if baseline.output != perturbed.output {
+        diffs.push("return data");
+    }
+    if diffs.is_empty() {
+        return Ok(());
+    }
+    Err(format!(
+        "NondeterministicTransaction: {} differ when the clock, randomness or HTTP responses change",
+        diffs.join(", ")
+    )
+    .into())
+}
+
+fn run(
+    session: &mut Session<PinkRuntime>,
+    state: &SharedState,
+    inputs: Option<TxInputs>,
+    call: &impl Fn() -> ContractExecResult,
+) -> Outcome {
+    let checkpoint = {
+        let mut state = state.lock().expect("Session state poisoned");
+        state.tx_inputs = inputs;
+        state.checkpoint()
+    };
+    let outcome = PinkRuntime::execute_in_mode(ExecMode::Transaction, || {
+        state::using(state.clone(), || {
+            session.sandbox().dry_run(|sandbox| {
+                sandbox.execute_with(|| {
+                    let output = call().result.encode();
+                    Outcome {
+                        storage_root: sp_io::storage::root(StateVersion::V1),
+                        events: System::events().iter().map(Encode::encode).collect(),
+                        output,
+                    }
+                })
+            })
+        })
+    });
+    // The checking runs should not show up in the session history.
+    let mut state = state.lock().expect("Session state poisoned");
+    state.tx_inputs = None;
+    state.rollback(checkpoint);
+    outcome
+}
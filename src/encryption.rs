@@ -0,0 +1,67 @@
+//! End-to-end encryption of queries and commands between a client and the worker.
+//!
+//! Both sides hold an sr25519 ECDH keypair. Like on workers, the AES-256-GCM key of a message is
+//! the raw Diffie-Hellman agreement between the sender's secret key and the receiver's public
+//! key, and the sender's public key travels with the ciphertext so that the receiver can agree
+//! on it too. Each message is sealed with a random IV.
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Nonce};
+use curve25519_dalek::scalar::Scalar;
+use pink::EcdhPublicKey;
+use scale::{Decode, Encode};
+use sp_core::{sr25519, Pair as _};
+
+use crate::Result;
+
+/// A message encrypted to the holder of an ECDH key.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct EncryptedData {
+    pub iv: [u8; 12],
+    /// The ECDH public key of the sender.
+    pub pubkey: EcdhPublicKey,
+    pub data: Vec<u8>,
+}
+
+impl EncryptedData {
+    /// Encrypt `plaintext` from the holder of `pair` to the holder of `peer`, with a random IV.
+    pub fn seal(pair: &sr25519::Pair, peer: &EcdhPublicKey, plaintext: &[u8]) -> Result<Self> {
+        let iv = Aes256Gcm::generate_nonce(&mut OsRng);
+        let data = cipher(pair, peer)?
+            .encrypt(&iv, plaintext)
+            .map_err(|err| format!("FailedToEncrypt: {err:?}"))?;
+        Ok(Self {
+            iv: iv.into(),
+            pubkey: pair.public().0,
+            data,
+        })
+    }
+
+    /// Decrypt a message sealed to the holder of `pair`.
+    pub fn open(&self, pair: &sr25519::Pair) -> Result<Vec<u8>> {
+        cipher(pair, &self.pubkey)?
+            .decrypt(Nonce::from_slice(&self.iv), &self.data[..])
+            .map_err(|err| format!("FailedToDecrypt: {err:?}").into())
+    }
+}
+
+/// The sr25519 Diffie-Hellman agreement of workers: the compressed product of our secret
+/// scalar and the peer's public point.
+fn agree(pair: &sr25519::Pair, peer: &EcdhPublicKey) -> Result<[u8; 32]> {
+    let secret = schnorrkel::SecretKey::from_bytes(&pair.to_raw_vec())
+        .map_err(|err| format!("InvalidEcdhKey: {err:?}"))?;
+    let public = schnorrkel::PublicKey::from_bytes(peer)
+        .map_err(|err| format!("InvalidEcdhPublicKey: {err:?}"))?;
+    let key: [u8; 32] = secret.to_bytes()[..32]
+        .try_into()
+        .expect("32 bytes out of 64");
+    let scalar = Option::<Scalar>::from(Scalar::from_canonical_bytes(key))
+        .ok_or("InvalidEcdhKey: non-canonical secret scalar")?;
+    Ok((scalar * public.as_point()).compress().to_bytes())
+}
+
+/// AES-256-GCM keyed with the agreed secret, as on workers.
+fn cipher(pair: &sr25519::Pair, peer: &EcdhPublicKey) -> Result<Aes256Gcm> {
+    let key = agree(pair, peer)?;
+    Ok(Aes256Gcm::new(&key.into()))
+}
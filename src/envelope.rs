@@ -0,0 +1,381 @@
+//! Signed queries in the format workers accept, to run a query as the account of a signer.
+//!
+//! A query is signed by a key named in a certificate. The certificate may in turn be signed by
+//! another key, forming a chain whose root is the account of the caller: wallets sign a
+//! certificate for a short-lived key once, which then signs the queries. The origin of the query
+//! is the account of the root key of the chain.
+//!
+//! Keys are sr25519, ed25519 or ecdsa. The account of an ecdsa key is the `blake2_256` hash of the
+//! compressed public key, as for `MultiSigner::Ecdsa`. The `WrapBytes` signature types sign the
+//! message wrapped in `<Bytes>` and `</Bytes>`, as browser wallets do for raw payloads.
+//!
+//! The signed message is the encoded [`ContractQuery`], which names the contract and carries a
+//! nonce. A worker rejects expired certificates and nonces it has already seen. Unlike on workers,
+//! queries are not encrypted, see [`Callable::encrypted_query`](crate::Callable::encrypted_query)
+//! for the encrypted channel.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use scale::{Decode, Encode};
+use sp_core::{ecdsa, ed25519, hashing::blake2_256, sr25519, Pair as _};
+
+use crate::accounts::TestAccount;
+use crate::types::AccountId;
+use crate::Result;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub enum SignatureType {
+    Ed25519,
+    Sr25519,
+    Ecdsa,
+    Ed25519WrapBytes,
+    Sr25519WrapBytes,
+    EcdsaWrapBytes,
+}
+
+impl SignatureType {
+    /// The type signing the message wrapped in `<Bytes>` and `</Bytes>`.
+    pub fn wrap_bytes(self) -> Self {
+        match self {
+            Self::Ed25519 | Self::Ed25519WrapBytes => Self::Ed25519WrapBytes,
+            Self::Sr25519 | Self::Sr25519WrapBytes => Self::Sr25519WrapBytes,
+            Self::Ecdsa | Self::EcdsaWrapBytes => Self::EcdsaWrapBytes,
+        }
+    }
+
+    fn wraps_bytes(self) -> bool {
+        matches!(
+            self,
+            Self::Ed25519WrapBytes | Self::Sr25519WrapBytes | Self::EcdsaWrapBytes
+        )
+    }
+}
+
+/// The key a certificate is issued for.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct CertificateBody {
+    pub pubkey: Vec<u8>,
+    /// The last block the certificate is valid at, if it is signed.
+    pub ttl: u32,
+    pub config_bits: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Certificate {
+    /// The encoded [`CertificateBody`], which is what the signature signs.
+    pub encoded_body: Vec<u8>,
+    /// The signature of the issuer, `None` for the root of a chain.
+    pub signature: Option<Box<Signature>>,
+}
+
+impl Certificate {
+    /// The unsigned certificate of `pubkey`, the root of a chain.
+    pub fn root(pubkey: Vec<u8>) -> Self {
+        let body = CertificateBody {
+            pubkey,
+            ttl: u32::MAX,
+            config_bits: 0,
+        };
+        Self {
+            encoded_body: body.encode(),
+            signature: None,
+        }
+    }
+
+    /// A certificate for `pubkey` signed by `issuer`, valid until block `ttl`.
+    pub fn issue(issuer: &dyn EnvelopeSigner, pubkey: Vec<u8>, ttl: u32) -> Self {
+        let body = CertificateBody {
+            pubkey,
+            ttl,
+            config_bits: 0,
+        };
+        let encoded_body = body.encode();
+        let signature = Signature::sign(issuer, &encoded_body);
+        Self {
+            encoded_body,
+            signature: Some(Box::new(signature)),
+        }
+    }
+
+    pub fn body(&self) -> Result<CertificateBody> {
+        CertificateBody::decode(&mut &self.encoded_body[..])
+            .map_err(|err| format!("InvalidCertificate: {err}").into())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct Signature {
+    /// The certificate of the signing key.
+    pub signed_by: Option<Box<Certificate>>,
+    pub signature_type: SignatureType,
+    pub signature: Vec<u8>,
+}
+
+impl Signature {
+    pub fn sign(signer: &dyn EnvelopeSigner, message: &[u8]) -> Self {
+        let signature_type = signer.signature_type();
+        let signature = if signature_type.wraps_bytes() {
+            signer.sign_message(&wrap_bytes(message))
+        } else {
+            signer.sign_message(message)
+        };
+        Self {
+            signed_by: Some(Box::new(signer.certificate())),
+            signature_type,
+            signature,
+        }
+    }
+
+    /// Check the signature of `message` and the certificates of the signing keys at
+    /// `block_number`, returning the accounts of the chain, root first.
+    pub fn verify(&self, message: &[u8], block_number: u32) -> Result<Vec<AccountId>> {
+        let certificate = self
+            .signed_by
+            .as_ref()
+            .ok_or("InvalidSignature: missing certificate")?;
+        let body = certificate.body()?;
+        let mut chain = match &certificate.signature {
+            Some(signature) => {
+                if body.ttl < block_number {
+                    return Err(format!(
+                        "CertificateExpired: valid until block {}, now {block_number}",
+                        body.ttl
+                    )
+                    .into());
+                }
+                signature.verify(&certificate.encoded_body, block_number)?
+            }
+            None => vec![],
+        };
+        let message = if self.signature_type.wraps_bytes() {
+            wrap_bytes(message)
+        } else {
+            message.to_vec()
+        };
+        chain.push(verify_signature(
+            self.signature_type,
+            &body.pubkey,
+            &self.signature,
+            &message,
+        )?);
+        Ok(chain)
+    }
+}
+
+/// A keypair able to sign queries.
+pub trait EnvelopeSigner {
+    fn signature_type(&self) -> SignatureType;
+    fn public_key(&self) -> Vec<u8>;
+    /// Sign `message` as is, the wrapping in `<Bytes>` is done by [`Signature::sign`].
+    fn sign_message(&self, message: &[u8]) -> Vec<u8>;
+    /// The certificate of the key, by default the root of a chain.
+    fn certificate(&self) -> Certificate {
+        Certificate::root(self.public_key())
+    }
+}
+
+impl EnvelopeSigner for sr25519::Pair {
+    fn signature_type(&self) -> SignatureType {
+        SignatureType::Sr25519
+    }
+    fn public_key(&self) -> Vec<u8> {
+        self.public().0.to_vec()
+    }
+    fn sign_message(&self, message: &[u8]) -> Vec<u8> {
+        self.sign(message).0.to_vec()
+    }
+}
+
+impl EnvelopeSigner for ed25519::Pair {
+    fn signature_type(&self) -> SignatureType {
+        SignatureType::Ed25519
+    }
+    fn public_key(&self) -> Vec<u8> {
+        self.public().0.to_vec()
+    }
+    fn sign_message(&self, message: &[u8]) -> Vec<u8> {
+        self.sign(message).0.to_vec()
+    }
+}
+
+impl EnvelopeSigner for ecdsa::Pair {
+    fn signature_type(&self) -> SignatureType {
+        SignatureType::Ecdsa
+    }
+    fn public_key(&self) -> Vec<u8> {
+        self.public().0.to_vec()
+    }
+    fn sign_message(&self, message: &[u8]) -> Vec<u8> {
+        self.sign(message).0.to_vec()
+    }
+}
+
+impl EnvelopeSigner for TestAccount {
+    fn signature_type(&self) -> SignatureType {
+        self.pair().signature_type()
+    }
+    fn public_key(&self) -> Vec<u8> {
+        self.pair().public_key()
+    }
+    fn sign_message(&self, message: &[u8]) -> Vec<u8> {
+        self.pair().sign_message(message)
+    }
+}
+
+/// A signer signing messages wrapped in `<Bytes>` and `</Bytes>`, like browser wallets.
+pub struct WrapBytes<S>(pub S);
+
+impl<S: EnvelopeSigner> EnvelopeSigner for WrapBytes<S> {
+    fn signature_type(&self) -> SignatureType {
+        self.0.signature_type().wrap_bytes()
+    }
+    fn public_key(&self) -> Vec<u8> {
+        self.0.public_key()
+    }
+    fn sign_message(&self, message: &[u8]) -> Vec<u8> {
+        self.0.sign_message(message)
+    }
+    fn certificate(&self) -> Certificate {
+        self.0.certificate()
+    }
+}
+
+/// A short-lived sr25519 key certified by an account, the way clients of workers sign queries.
+pub struct CertifiedSigner {
+    key: sr25519::Pair,
+    certificate: Certificate,
+}
+
+impl CertifiedSigner {
+    /// Generate a key and have `issuer` sign its certificate, valid until block `ttl`.
+    pub fn issue(issuer: &dyn EnvelopeSigner, ttl: u32) -> Self {
+        let (key, _) = sr25519::Pair::generate();
+        let certificate = Certificate::issue(issuer, key.public_key(), ttl);
+        Self { key, certificate }
+    }
+}
+
+impl EnvelopeSigner for CertifiedSigner {
+    fn signature_type(&self) -> SignatureType {
+        SignatureType::Sr25519
+    }
+    fn public_key(&self) -> Vec<u8> {
+        self.key.public_key()
+    }
+    fn sign_message(&self, message: &[u8]) -> Vec<u8> {
+        self.key.sign_message(message)
+    }
+    fn certificate(&self) -> Certificate {
+        self.certificate.clone()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct ContractQueryHead {
+    pub id: AccountId,
+    /// Makes each signed query unique, workers reject nonces they have seen.
+    pub nonce: [u8; 32],
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct ContractQuery {
+    pub head: ContractQueryHead,
+    /// The encoded message selector and arguments.
+    pub data: Vec<u8>,
+}
+
+/// A query to a contract together with the signature of its caller.
+///
+/// The fields are public so that tests can tamper with a signed query.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct QueryEnvelope {
+    /// The encoded [`ContractQuery`], which is what the signature signs.
+    pub encoded_query: Vec<u8>,
+    pub signature: Signature,
+}
+
+impl QueryEnvelope {
+    /// Sign a query of `contract` with a fresh nonce.
+    pub fn new(contract: AccountId, input: Vec<u8>, signer: &dyn EnvelopeSigner) -> Self {
+        let query = ContractQuery {
+            head: ContractQueryHead {
+                id: contract,
+                nonce: next_nonce(),
+            },
+            data: input,
+        };
+        Self::sign(&query, signer)
+    }
+
+    pub fn sign(query: &ContractQuery, signer: &dyn EnvelopeSigner) -> Self {
+        let encoded_query = query.encode();
+        let signature = Signature::sign(signer, &encoded_query);
+        Self {
+            encoded_query,
+            signature,
+        }
+    }
+
+    pub fn query(&self) -> Result<ContractQuery> {
+        ContractQuery::decode(&mut &self.encoded_query[..])
+            .map_err(|err| format!("InvalidQuery: {err}").into())
+    }
+
+    /// Check the signature at `block_number` and return the origin of the query, the account of
+    /// the root of the certificate chain.
+    pub fn verify(&self, block_number: u32) -> Result<(AccountId, ContractQuery)> {
+        let chain = self.signature.verify(&self.encoded_query, block_number)?;
+        let origin = chain
+            .into_iter()
+            .next()
+            .ok_or("InvalidSignature: no signer")?;
+        Ok((origin, self.query()?))
+    }
+}
+
+/// Check a signature of `message` by `pubkey` and return the account of the key.
+fn verify_signature(
+    signature_type: SignatureType,
+    pubkey: &[u8],
+    signature: &[u8],
+    message: &[u8],
+) -> Result<AccountId> {
+    let invalid = || format!("InvalidSignature: {signature_type:?} signature mismatch");
+    let malformed = || format!("InvalidSignature: malformed {signature_type:?} key or signature");
+    match signature_type {
+        SignatureType::Sr25519 | SignatureType::Sr25519WrapBytes => {
+            let pubkey = sr25519::Public::try_from(pubkey).map_err(|_| malformed())?;
+            let signature = sr25519::Signature::try_from(signature).map_err(|_| malformed())?;
+            if !sr25519::Pair::verify(&signature, message, &pubkey) {
+                return Err(invalid().into());
+            }
+            Ok(pubkey.into())
+        }
+        SignatureType::Ed25519 | SignatureType::Ed25519WrapBytes => {
+            let pubkey = ed25519::Public::try_from(pubkey).map_err(|_| malformed())?;
+            let signature = ed25519::Signature::try_from(signature).map_err(|_| malformed())?;
+            if !ed25519::Pair::verify(&signature, message, &pubkey) {
+                return Err(invalid().into());
+            }
+            Ok(pubkey.into())
+        }
+        SignatureType::Ecdsa | SignatureType::EcdsaWrapBytes => {
+            let pubkey = ecdsa::Public::try_from(pubkey).map_err(|_| malformed())?;
+            let signature = ecdsa::Signature::try_from(signature).map_err(|_| malformed())?;
+            if !ecdsa::Pair::verify(&signature, message, &pubkey) {
+                return Err(invalid().into());
+            }
+            Ok(blake2_256(pubkey.as_ref()).into())
+        }
+    }
+}
+
+fn wrap_bytes(message: &[u8]) -> Vec<u8> {
+    [&b"<Bytes>"[..], message, &b"</Bytes>"[..]].concat()
+}
+
+/// A nonce not used before by this process.
+fn next_nonce() -> [u8; 32] {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    blake2_256(&(b"query-nonce", COUNTER.fetch_add(1, Ordering::Relaxed)).encode())
+}
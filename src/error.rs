@@ -1,6 +1,6 @@
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Error(String);
 
 impl std::fmt::Display for Error {
@@ -19,3 +19,38 @@ impl From<&str> for Error {
         Self(s.to_string())
     }
 }
+
+/// Error returned by calls to messages that return a `Result`.
+///
+/// `Contract` carries the error returned by the contract itself, decoded from the revert
+/// data, while `Runtime` covers everything that went wrong before the message returned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CallError<E> {
+    Contract(E),
+    Runtime(Error),
+}
+
+impl<E> CallError<E> {
+    /// Returns the contract error, if any.
+    pub fn contract_error(self) -> Option<E> {
+        match self {
+            CallError::Contract(err) => Some(err),
+            CallError::Runtime(_) => None,
+        }
+    }
+}
+
+impl<E: std::fmt::Debug> std::fmt::Display for CallError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CallError::Contract(err) => write!(f, "Contract error: {err:?}"),
+            CallError::Runtime(err) => write!(f, "{err}"),
+        }
+    }
+}
+impl<E: std::fmt::Debug> std::error::Error for CallError<E> {}
+impl<E> From<Error> for CallError<E> {
+    fn from(err: Error) -> Self {
+        CallError::Runtime(err)
+    }
+}
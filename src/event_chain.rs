@@ -0,0 +1,85 @@
+//! The event chain of a cluster.
+//!
+//! Like on a worker, every transaction emitting contract events produces an event block, which
+//! is numbered by `NextEventBlockNumber` and links to the previous block through
+//! `LastEventBlockHash`. The blocks are kept in the sandbox storage, so they belong to the
+//! cluster they were produced in and are captured by snapshots.
+
+use frame_support::storage::unhashed;
+use scale::{Decode, Encode};
+
+use crate::runtime::{Pink, RuntimeEvent, System};
+use crate::types::{AccountId, BlockNumber, Hash};
+
+/// The storage key holding the event blocks produced so far.
+pub(crate) const EVENT_BLOCKS_KEY: &[u8] = b":pink-drink:event_blocks:";
+
+/// The events emitted by a transaction.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct EventBlock {
+    pub number: u64,
+    /// The hash of the previous event block, zero for the first one.
+    pub parent_hash: Hash,
+    /// The block in which the transaction was executed.
+    pub block_number: BlockNumber,
+    pub origin: AccountId,
+    /// The contract called or instantiated by the transaction.
+    pub entry_contract: AccountId,
+    pub events: Vec<ContractEvent>,
+}
+
+impl EventBlock {
+    pub fn hash(&self) -> Hash {
+        sp_core::hashing::blake2_256(&self.encode()).into()
+    }
+}
+
+/// An event emitted by a contract. Pink system events are not part of the event chain.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct ContractEvent {
+    pub contract: AccountId,
+    pub topics: Vec<Hash>,
+    pub data: Vec<u8>,
+}
+
+/// The event blocks produced so far in the cluster.
+pub(crate) fn blocks() -> Vec<EventBlock> {
+    unhashed::get(EVENT_BLOCKS_KEY).unwrap_or_default()
+}
+
+/// Produce an event block from the contract events deposited by a transaction since
+/// `first_event`, if any.
+pub(crate) fn emit(origin: &AccountId, entry_contract: &AccountId, first_event: u32) {
+    let pink_topic: Hash = pink::PinkEvent::event_topic().into();
+    let events: Vec<_> = System::events()
+        .into_iter()
+        .skip(first_event as usize)
+        .filter(|record| !record.topics.contains(&pink_topic))
+        .filter_map(|record| match record.event {
+            RuntimeEvent::Contracts(pallet_contracts::Event::ContractEmitted {
+                contract,
+                data,
+            }) => Some(ContractEvent {
+                contract,
+                topics: record.topics,
+                data,
+            }),
+            _ => None,
+        })
+        .collect();
+    if events.is_empty() {
+        return;
+    }
+    let block = EventBlock {
+        number: Pink::take_next_event_block_number(),
+        parent_hash: Pink::last_event_block_hash(),
+        block_number: System::block_number(),
+        origin: origin.clone(),
+        entry_contract: entry_contract.clone(),
+        events,
+    };
+    Pink::set_last_event_block_hash(block.hash());
+    let mut blocks = blocks();
+    blocks.push(block);
+    unhashed::put(EVENT_BLOCKS_KEY, &blocks);
+}
@@ -0,0 +1,307 @@
+//! Override chain extension functions from tests.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use frame_support::sp_runtime::DispatchError;
+use pink::chain_extension::func_ids;
+use scale::{Decode, Encode};
+
+use crate::types::AccountId;
+
+/// The functions of the pink chain extension, see `pink::chain_extension::PinkExtBackend`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ExtFunction {
+    HttpRequest,
+    Sign,
+    Verify,
+    DeriveSr25519Key,
+    GetPublicKey,
+    CacheSet,
+    CacheSetExpiration,
+    CacheGet,
+    CacheRemove,
+    Log,
+    Getrandom,
+    IsInTransaction,
+    EcdsaSignPrehashed,
+    EcdsaVerifyPrehashed,
+    SystemContractId,
+    BalanceOf,
+    UntrustedMillisSinceUnixEpoch,
+    WorkerPubkey,
+    CodeExists,
+    ImportLatestSystemCode,
+    RuntimeVersion,
+    CurrentEventChainHead,
+    BatchHttpRequest,
+    JsEval,
+    WorkerSgxQuote,
+}
+
+impl ExtFunction {
+    pub const ALL: [ExtFunction; 25] = [
+        ExtFunction::HttpRequest,
+        ExtFunction::Sign,
+        ExtFunction::Verify,
+        ExtFunction::DeriveSr25519Key,
+        ExtFunction::GetPublicKey,
+        ExtFunction::CacheSet,
+        ExtFunction::CacheSetExpiration,
+        ExtFunction::CacheGet,
+        ExtFunction::CacheRemove,
+        ExtFunction::Log,
+        ExtFunction::Getrandom,
+        ExtFunction::IsInTransaction,
+        ExtFunction::EcdsaSignPrehashed,
+        ExtFunction::EcdsaVerifyPrehashed,
+        ExtFunction::SystemContractId,
+        ExtFunction::BalanceOf,
+        ExtFunction::UntrustedMillisSinceUnixEpoch,
+        ExtFunction::WorkerPubkey,
+        ExtFunction::CodeExists,
+        ExtFunction::ImportLatestSystemCode,
+        ExtFunction::RuntimeVersion,
+        ExtFunction::CurrentEventChainHead,
+        ExtFunction::BatchHttpRequest,
+        ExtFunction::JsEval,
+        ExtFunction::WorkerSgxQuote,
+    ];
+
+    /// The `func_id` contracts call the function with.
+    pub fn func_id(self) -> u32 {
+        match self {
+            ExtFunction::HttpRequest => func_ids::HTTP_REQUEST,
+            ExtFunction::Sign => func_ids::SIGN,
+            ExtFunction::Verify => func_ids::VERIFY,
+            ExtFunction::DeriveSr25519Key => func_ids::DERIVE_SR25519_KEY,
+            ExtFunction::GetPublicKey => func_ids::GET_PUBLIC_KEY,
+            ExtFunction::CacheSet => func_ids::CACHE_SET,
+            ExtFunction::CacheSetExpiration => func_ids::CACHE_SET_EXPIRATION,
+            ExtFunction::CacheGet => func_ids::CACHE_GET,
+            ExtFunction::CacheRemove => func_ids::CACHE_REMOVE,
+            ExtFunction::Log => func_ids::LOG,
+            ExtFunction::Getrandom => func_ids::GETRANDOM,
+            ExtFunction::IsInTransaction => func_ids::IS_IN_TRANSACTION,
+            ExtFunction::EcdsaSignPrehashed => func_ids::ECDSA_SIGN_PREHASHED,
+            ExtFunction::EcdsaVerifyPrehashed => func_ids::ECDSA_VERIFY_PREHASHED,
+            ExtFunction::SystemContractId => func_ids::SYSTEM_CONTRACT_ID,
+            ExtFunction::BalanceOf => func_ids::BALANCE_OF,
+            ExtFunction::UntrustedMillisSinceUnixEpoch => {
+                func_ids::UNTRUSTED_MILLIS_SINCE_UNIX_EPOCH
+            }
+            ExtFunction::WorkerPubkey => func_ids::WORKER_PUBKEY,
+            ExtFunction::CodeExists => func_ids::CODE_EXISTS,
+            ExtFunction::ImportLatestSystemCode => func_ids::IMPORT_LATEST_SYSTEM_CODE,
+            ExtFunction::RuntimeVersion => func_ids::RUNTIME_VERSION,
+            ExtFunction::CurrentEventChainHead => func_ids::CURRENT_EVENT_CHAIN_HEAD,
+            ExtFunction::BatchHttpRequest => func_ids::BATCH_HTTP_REQUEST,
+            ExtFunction::JsEval => func_ids::JS_EVAL,
+            ExtFunction::WorkerSgxQuote => func_ids::WORKER_SGX_QUOTE,
+        }
+    }
+
+    pub fn from_func_id(func_id: u32) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|function| function.func_id() == func_id)
+    }
+
+    /// The name of the `PinkExtBackend` method.
+    pub fn name(self) -> &'static str {
+        match self {
+            ExtFunction::HttpRequest => "http_request",
+            ExtFunction::Sign => "sign",
+            ExtFunction::Verify => "verify",
+            ExtFunction::DeriveSr25519Key => "derive_sr25519_key",
+            ExtFunction::GetPublicKey => "get_public_key",
+            ExtFunction::CacheSet => "cache_set",
+            ExtFunction::CacheSetExpiration => "cache_set_expiration",
+            ExtFunction::CacheGet => "cache_get",
+            ExtFunction::CacheRemove => "cache_remove",
+            ExtFunction::Log => "log",
+            ExtFunction::Getrandom => "getrandom",
+            ExtFunction::IsInTransaction => "is_in_transaction",
+            ExtFunction::EcdsaSignPrehashed => "ecdsa_sign_prehashed",
+            ExtFunction::EcdsaVerifyPrehashed => "ecdsa_verify_prehashed",
+            ExtFunction::SystemContractId => "system_contract_id",
+            ExtFunction::BalanceOf => "balance_of",
+            ExtFunction::UntrustedMillisSinceUnixEpoch => "untrusted_millis_since_unix_epoch",
+            ExtFunction::WorkerPubkey => "worker_pubkey",
+            ExtFunction::CodeExists => "code_exists",
+            ExtFunction::ImportLatestSystemCode => "import_latest_system_code",
+            ExtFunction::RuntimeVersion => "runtime_version",
+            ExtFunction::CurrentEventChainHead => "current_event_chain_head",
+            ExtFunction::BatchHttpRequest => "batch_http_request",
+            ExtFunction::JsEval => "js_eval",
+            ExtFunction::WorkerSgxQuote => "worker_sgx_quote",
+        }
+    }
+}
+
+impl std::fmt::Display for ExtFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A mocked chain extension function, taking the SCALE encoded arguments and returning the SCALE
+/// encoded return value.
+pub(crate) type ExtHandler = Arc<dyn Fn(&[u8]) -> Result<Vec<u8>, DispatchError> + Send + Sync>;
+
+/// Wrap a typed closure into an [`ExtHandler`].
+///
+/// The arguments are decoded as the tuple of the method arguments, e.g. `(AccountId,)` for
+/// `balance_of`.
+pub(crate) fn handler<Args, Ret>(f: impl Fn(Args) -> Ret + Send + Sync + 'static) -> ExtHandler
+where
+    Args: Decode,
+    Ret: Encode,
+{
+    Arc::new(move |input| {
+        let args = Args::decode(&mut &input[..])
+            .map_err(|_| DispatchError::Other("MockedExtCallBadInput"))?;
+        Ok(f(args).encode())
+    })
+}
+
+/// Mocks of the session, by `func_id` and optionally by contract.
+#[derive(Default)]
+pub(crate) struct ExtMocks {
+    handlers: BTreeMap<(u32, Option<AccountId>), ExtHandler>,
+}
+
+impl ExtMocks {
+    pub fn insert(
+        &mut self,
+        function: ExtFunction,
+        contract: Option<AccountId>,
+        handler: ExtHandler,
+    ) {
+        self.handlers
+            .insert((function.func_id(), contract), handler);
+    }
+
+    pub fn remove(&mut self, function: ExtFunction, contract: Option<AccountId>) {
+        self.handlers.remove(&(function.func_id(), contract));
+    }
+
+    pub fn clear(&mut self) {
+        self.handlers.clear();
+    }
+
+    /// The mock for a call from `contract`, preferring the ones scoped to the contract.
+    pub fn get(&self, func_id: u32, contract: &AccountId) -> Option<ExtHandler> {
+        self.handlers
+            .get(&(func_id, Some(contract.clone())))
+            .or_else(|| self.handlers.get(&(func_id, None)))
+            .cloned()
+    }
+}
+
+/// How an injected fault makes a chain extension call fail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fault {
+    /// The call returns the error, trapping the contract.
+    Error(DispatchError),
+    /// The call never returns, like a worker aborting a stuck call: all the remaining gas is
+    /// consumed and the contract runs out of gas.
+    Timeout,
+    /// The call succeeds but writes the given bytes, which should not decode as the return value.
+    MalformedOutput(Vec<u8>),
+}
+
+/// A fault injected into the calls to a chain extension function.
+///
+/// By default the fault hits the next call to the function from any contract, once.
+#[derive(Debug, Clone)]
+pub struct FaultInjection {
+    function: ExtFunction,
+    contract: Option<AccountId>,
+    skip: u32,
+    times: Option<u32>,
+    fault: Fault,
+}
+
+impl FaultInjection {
+    pub fn new(function: ExtFunction, fault: Fault) -> Self {
+        Self {
+            function,
+            contract: None,
+            skip: 0,
+            times: Some(1),
+            fault,
+        }
+    }
+
+    /// Only hit calls made by `contract`.
+    pub fn for_contract<A: Encode>(mut self, contract: &A) -> Self {
+        let contract =
+            AccountId::decode(&mut &contract.encode()[..]).expect("Failed to decode account id");
+        self.contract = Some(contract);
+        self
+    }
+
+    /// Let the first `calls` matching calls through.
+    pub fn after(mut self, calls: u32) -> Self {
+        self.skip = calls;
+        self
+    }
+
+    /// Hit `n` matching calls instead of one.
+    pub fn times(mut self, n: u32) -> Self {
+        self.times = Some(n);
+        self
+    }
+
+    /// Hit every matching call.
+    pub fn always(mut self) -> Self {
+        self.times = None;
+        self
+    }
+
+    fn matches(&self, func_id: u32, contract: &AccountId) -> bool {
+        self.function.func_id() == func_id
+            && self
+                .contract
+                .as_ref()
+                .is_none_or(|target| target == contract)
+    }
+}
+
+/// Faults injected into the session.
+#[derive(Default, Clone)]
+pub(crate) struct Faults {
+    injections: Vec<FaultInjection>,
+}
+
+impl Faults {
+    pub fn inject(&mut self, injection: FaultInjection) {
+        self.injections.push(injection);
+    }
+
+    pub fn clear(&mut self) {
+        self.injections.clear();
+    }
+
+    /// Count a call against the injections, returning the fault it hits if any.
+    pub fn hit(&mut self, func_id: u32, contract: &AccountId) -> Option<Fault> {
+        let index = self
+            .injections
+            .iter()
+            .position(|injection| injection.matches(func_id, contract))?;
+        let injection = &mut self.injections[index];
+        if injection.skip > 0 {
+            injection.skip -= 1;
+            return None;
+        }
+        let fault = injection.fault.clone();
+        if let Some(times) = &mut injection.times {
+            *times = times.saturating_sub(1);
+            if *times == 0 {
+                self.injections.remove(index);
+            }
+        }
+        Some(fault)
+    }
+}
@@ -0,0 +1,256 @@
+//! Install contract state exported from a Phala worker or from chain state.
+//!
+//! A dump is a JSON document describing a single contract:
+//!
+//! ```json
+//! {
+//!     "cluster_id": "0x0000…0000",
+//!     "deployer": "0xd435…a27d",
+//!     "salt": "0x",
+//!     "code": "0x0061736d…",
+//!     "balance": 1000000000000,
+//!     "key_format": "raw",
+//!     "storage": [
+//!         ["0x00000000", "0x0102…"]
+//!     ]
+//! }
+//! ```
+//!
+//! - `cluster_id` (optional): the cluster the contract was deployed in. Defaults to the cluster
+//!   of the session. Together with `deployer`, the code hash and `salt` it determines the
+//!   contract address, the same way `pallet_pink`'s `AddressGenerator` does.
+//! - `deployer`: the 32 bytes account that instantiated the contract.
+//! - `salt` (optional): the salt used at instantiation. Defaults to empty.
+//! - `code`: the wasm code of the contract.
+//! - `balance` (optional): free balance to endow the contract with.
+//! - `determinism` (optional): `"enforced"` (default) or `"relaxed"`, the determinism the code
+//!   is uploaded with. Code using floats must be uploaded with relaxed determinism.
+//! - `key_format` (optional): `"raw"` (default) if the storage keys are the keys passed to
+//!   `seal_set_storage` by the contract, e.g. `0x00000000` for the ink! root key, or
+//!   `"child_trie"` if they are the hashed keys of the contract child trie.
+//! - `storage`: the key/value pairs of the contract storage.
+//!
+//! All byte strings are hex encoded with an optional `0x` prefix.
+//!
+//! The storage is written by the contract itself, so the deployer pays the storage deposit and
+//! the contract info counts the items as if the contract had written them.
+
+use std::path::Path;
+
+use frame_support::traits::Currency;
+use serde::Deserialize;
+
+use crate::runtime::{Balances, Contracts, Pink, RuntimeOrigin};
+use crate::storage;
+use crate::types::{AccountId, Balance, Hash};
+use crate::{Determinism, PinkRuntime, Result};
+
+/// A contract whose constructor does nothing and whose `call` writes a storage entry, given as
+/// the key length (`u32`, little endian), the key and the value. Instantiated at the target
+/// address to write the storage, then replaced by the imported code and removed.
+///
+/// ```wat
+/// (module
+///   (import "seal0" "seal_input" (func $input (param i32 i32)))
+///   (import "seal2" "set_storage" (func $set_storage (param i32 i32 i32 i32) (result i32)))
+///   (import "env" "memory" (memory 16 16))
+///   (func (export "deploy"))
+///   (func (export "call")
+///     (i32.store (i32.const 0) (i32.const 1048568))
+///     (call $input (i32.const 8) (i32.const 0))
+///     (drop (call $set_storage
+///       (i32.const 12)
+///       (i32.load (i32.const 8))
+///       (i32.add (i32.const 12) (i32.load (i32.const 8)))
+///       (i32.sub (i32.sub (i32.load (i32.const 0)) (i32.const 4)) (i32.load (i32.const 8)))))))
+/// ```
+const STORAGE_WRITER: &[u8] = &[
+    0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // magic, version
+    0x01, 0x11, 0x03, 0x60, 0x02, 0x7f, 0x7f, 0x00, 0x60, 0x04, 0x7f, 0x7f, 0x7f, 0x7f, 0x01, 0x7f,
+    0x60, 0x00, 0x00, // type section: (i32, i32), (i32, i32, i32, i32) -> i32, ()
+    0x02, 0x37, 0x03, 0x05, b's', b'e', b'a', b'l', b'0', 0x0a, b's', b'e', b'a', b'l', b'_', b'i',
+    b'n', b'p', b'u', b't', 0x00, 0x00, 0x05, b's', b'e', b'a', b'l', b'2', 0x0b, b's', b'e', b't',
+    b'_', b's', b't', b'o', b'r', b'a', b'g', b'e', 0x00, 0x01, 0x03, b'e', b'n', b'v', 0x06, b'm',
+    b'e', b'm', b'o', b'r', b'y', 0x02, 0x01, 0x10, 0x10, // import section
+    0x03, 0x03, 0x02, 0x02, 0x02, // function section
+    0x07, 0x11, 0x02, 0x06, b'd', b'e', b'p', b'l', b'o', b'y', 0x00, 0x02, 0x04, b'c', b'a', b'l',
+    b'l', 0x00, 0x03, // export section
+    0x0a, 0x36, 0x02, 0x02, 0x00, 0x0b, // code section: deploy
+    0x31, 0x00, 0x41, 0x00, 0x41, 0xf8, 0xff, 0x3f, 0x36, 0x02, 0x00, 0x41, 0x08, 0x41, 0x00, 0x10,
+    0x00, 0x41, 0x0c, 0x41, 0x08, 0x28, 0x02, 0x00, 0x41, 0x0c, 0x41, 0x08, 0x28, 0x02, 0x00, 0x6a,
+    0x41, 0x00, 0x28, 0x02, 0x00, 0x41, 0x04, 0x6b, 0x41, 0x08, 0x28, 0x02, 0x00, 0x6b, 0x10, 0x01,
+    0x1a, 0x0b, // code section: call
+];
+
+/// How the storage keys of a [`ContractDump`] are given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeyFormat {
+    /// Keys as seen by the contract.
+    #[default]
+    Raw,
+    /// Hashed keys of the contract child trie.
+    ChildTrie,
+}
+
+/// The state of a single contract, see the [module docs](self) for the file format.
+#[derive(Debug, Clone)]
+pub struct ContractDump {
+    pub cluster_id: Option<Hash>,
+    pub deployer: AccountId,
+    pub salt: Vec<u8>,
+    pub code: Vec<u8>,
+    pub balance: Balance,
+    /// The determinism the code is uploaded with.
+    pub determinism: Determinism,
+    pub key_format: KeyFormat,
+    pub storage: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+#[derive(Deserialize)]
+struct DumpFile {
+    cluster_id: Option<String>,
+    deployer: String,
+    #[serde(default)]
+    salt: String,
+    code: String,
+    #[serde(default)]
+    balance: Balance,
+    determinism: Option<String>,
+    key_format: Option<String>,
+    storage: Vec<(String, String)>,
+}
+
+fn decode_hex(field: &str, value: &str) -> Result<Vec<u8>> {
+    hex::decode(value.trim_start_matches("0x"))
+        .map_err(|err| format!("InvalidContractDump: bad hex in `{field}`: {err}").into())
+}
+
+fn decode_hex32(field: &str, value: &str) -> Result<[u8; 32]> {
+    decode_hex(field, value)?
+        .try_into()
+        .map_err(|_| format!("InvalidContractDump: `{field}` must be 32 bytes").into())
+}
+
+impl ContractDump {
+    pub fn from_json(json: &str) -> Result<Self> {
+        let file: DumpFile =
+            serde_json::from_str(json).map_err(|err| format!("InvalidContractDump: {err}"))?;
+        let key_format = match file.key_format.as_deref() {
+            None | Some("raw") => KeyFormat::Raw,
+            Some("child_trie") => KeyFormat::ChildTrie,
+            Some(other) => {
+                return Err(format!("InvalidContractDump: unknown key_format `{other}`").into())
+            }
+        };
+        let determinism = match file.determinism.as_deref() {
+            None | Some("enforced") => Determinism::Enforced,
+            Some("relaxed") => Determinism::Relaxed,
+            Some(other) => {
+                return Err(format!("InvalidContractDump: unknown determinism `{other}`").into())
+            }
+        };
+        let storage = file
+            .storage
+            .iter()
+            .map(|(key, value)| Ok((decode_hex("storage", key)?, decode_hex("storage", value)?)))
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            cluster_id: file
+                .cluster_id
+                .map(|id| decode_hex32("cluster_id", &id).map(Hash::from))
+                .transpose()?,
+            deployer: decode_hex32("deployer", &file.deployer)?.into(),
+            salt: decode_hex("salt", &file.salt)?,
+            code: decode_hex("code", &file.code)?,
+            balance: file.balance,
+            determinism,
+            key_format,
+            storage,
+        })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let json = std::fs::read_to_string(path.as_ref()).map_err(|err| {
+            format!(
+                "FailedToReadContractDump({}): {err}",
+                path.as_ref().display()
+            )
+        })?;
+        Self::from_json(&json)
+    }
+}
+
+/// Install the contract described by `dump`. Must be called in a transaction.
+pub(crate) fn install(dump: &ContractDump) -> Result<AccountId> {
+    let deployer = dump.deployer.clone();
+    let deterministic = dump.determinism == Determinism::Enforced;
+    let code_hash = PinkRuntime::upload_code(deployer.clone(), dump.code.clone(), deterministic)
+        .map_err(|err| format!("FailedToUploadCode: {err}"))?;
+    let cluster_id = dump.cluster_id.unwrap_or_else(Pink::cluster_id);
+    let address = Pink::contract_address_in(&cluster_id, &deployer, &code_hash, &dump.salt);
+    if storage::contract_trie_id(&address).is_some() {
+        return Err(format!("ContractAlreadyExists: {address}").into());
+    }
+
+    let writer_hash = PinkRuntime::upload_code(deployer.clone(), STORAGE_WRITER.to_vec(), true)
+        .map_err(|err| format!("FailedToUploadStubCode: {err}"))?;
+    Pink::set_next_contract_address(Some(address.clone()));
+    let instantiated = PinkRuntime::instantiate(
+        deployer.clone(),
+        0,
+        u64::MAX,
+        None,
+        writer_hash,
+        vec![],
+        dump.salt.clone(),
+    );
+    Pink::set_next_contract_address(None);
+    let instantiated = instantiated.map_err(|err| format!("FailedToInstantiateStub: {err}"))?;
+    if instantiated != address {
+        return Err(format!(
+            "UnexpectedContractAddress: expected {address}, instantiated at {instantiated}"
+        )
+        .into());
+    }
+
+    for (key, value) in &dump.storage {
+        let key = match dump.key_format {
+            KeyFormat::Raw => key.clone(),
+            KeyFormat::ChildTrie => raw_key(key)?,
+        };
+        let input = [&(key.len() as u32).to_le_bytes()[..], &key, value].concat();
+        PinkRuntime::call(
+            deployer.clone(),
+            address.clone(),
+            0,
+            u64::MAX,
+            None,
+            input,
+            true,
+        )
+        .map_err(|err| format!("FailedToWriteStorage(0x{}): {err}", hex::encode(&key)))?;
+    }
+    Contracts::set_code(RuntimeOrigin::root(), address.clone(), code_hash)
+        .map_err(|err| format!("FailedToSetCode: {err:?}"))?;
+    // No contract uses the stub anymore, removing it refunds its deposit to the deployer.
+    Contracts::remove_code(RuntimeOrigin::signed(deployer), writer_hash)
+        .map_err(|err| format!("FailedToRemoveStubCode: {err:?}"))?;
+    if dump.balance > 0 {
+        let _ = Balances::deposit_creating(&address, dump.balance);
+    }
+    Ok(address)
+}
+
+/// The key passed to `seal_set_storage` for a key of the contract child trie.
+fn raw_key(child_key: &[u8]) -> Result<Vec<u8>> {
+    storage::strip_contract_key(child_key)
+        .filter(|key| storage::contract_key(key) == child_key)
+        .map(<[u8]>::to_vec)
+        .ok_or_else(|| {
+            format!(
+                "InvalidContractDump: 0x{} is not a contract child trie key",
+                hex::encode(child_key)
+            )
+            .into()
+        })
+}
@@ -1,7 +1,18 @@
 use crate::{
+    cluster, determinism,
+    encryption::EncryptedData,
+    envelope::{EnvelopeSigner, QueryEnvelope},
+    event_chain::{self, EventBlock},
+    ext::{self, ExtFunction, FaultInjection},
+    import::{self, ContractDump},
+    permission::{self, PermissionDenial},
     runtime::{ContractExecResult, ContractInstantiateResult},
-    types::ExecMode,
-    PinkRuntime, Result,
+    snapshot::{self, Snapshot},
+    state::{self, SeededRng, WorkerId},
+    storage,
+    types::{BlockNumber, ClusterId, ExecMode, NondeterminismPolicy},
+    CallError, CallTrace, ContractLog, ContractMetadata, NondeterministicCall, PinkRuntime, Result,
+    WorkerIdentity,
 };
 
 use ::ink::{
@@ -14,9 +25,23 @@ use ::ink::{
     },
     primitives::Hash,
 };
-use drink::{errors::MessageResult, runtime::AccountIdFor, session::Session, ContractBundle};
+use drink::{
+    errors::MessageResult,
+    runtime::{AccountIdFor, Runtime as _},
+    session::Session,
+    ContractBundle,
+};
+use frame_support::sp_runtime::DispatchError;
+use frame_support::traits::{Currency, ExistenceRequirement};
+use frame_support::weights::Weight;
+use pallet_contracts::Determinism;
+use pallet_contracts_primitives::StorageDeposit;
 use pink::Balance;
+use pink::EcdhPublicKey;
 use scale::{Decode, Encode};
+use sp_core::{sr25519, Pair as _, H256};
+use std::path::Path;
+use std::sync::Arc;
 
 type PinkSession = Session<PinkRuntime>;
 type AccountId = AccountIdFor<PinkRuntime>;
@@ -28,49 +53,808 @@ pub fn code_hash(wasm: &[u8]) -> [u8; 32] {
 const DEFAULT_QUERY_GAS_LIMIT: u64 = 50_000_000_000_000;
 const DEFAULT_TX_GAS_LIMIT: u64 = 2500_000_000_000;
 
+/// Calls, accounts and the diagnostics of a session.
 pub trait SessionExt {
-    fn actor(&mut self) -> AccountId;
+    /// The account calls and transactions are made as.
+    fn actor(&self) -> AccountId;
+    /// Run `f` against the session state as a query: state changes are discarded and the
+    /// extension behaves like on a worker serving a query.
     fn query<T>(&mut self, f: impl FnOnce() -> T) -> T;
+    /// Run `f` as a transaction, committing its state changes and applying the cache operations
+    /// of the contracts on every worker.
     fn tx<T>(&mut self, f: impl FnOnce() -> T) -> T;
+    /// Dry-run `f` as a transaction, discarding any state changes and anything it recorded in
+    /// the session, such as debug messages, logs and traces.
+    fn estimate<T>(&mut self, f: impl FnOnce() -> T) -> T;
+    /// Run `f` with `account` as the actor, restoring the previous actor afterwards.
+    fn as_actor<T>(&mut self, account: impl Into<AccountId>, f: impl FnOnce(&mut Self) -> T) -> T;
+    /// Mint `amount` into the free balance of `account`.
+    fn fund(&mut self, account: impl Into<AccountId>, amount: Balance);
+    /// Transfer `amount` from the free balance of `from` to `to`.
+    fn transfer(
+        &mut self,
+        from: impl Into<AccountId>,
+        to: impl Into<AccountId>,
+        amount: Balance,
+    ) -> Result<()>;
+    /// The free balance of `account`.
+    fn free_balance(&mut self, account: impl Into<AccountId>) -> Balance;
+    /// Register `contract` as the driver `name` in the system contract, as the session actor.
     fn set_driver<A: Encode>(&mut self, name: &str, contract: &A) -> Result<()>;
+    /// Debug buffers of all calls and instantiations made through this trait so far.
+    fn debug_messages(&mut self) -> Vec<String>;
+    /// Return and clear the accumulated debug buffers.
+    fn take_debug_messages(&mut self) -> Vec<String>;
+    /// Logs emitted by contracts through the chain extension so far.
+    fn contract_logs(&mut self) -> Vec<ContractLog>;
+    /// Logs emitted by the given contract through the chain extension so far.
+    fn contract_logs_of<A: Encode>(&mut self, contract: &A) -> Vec<ContractLog>;
+    /// Forget the contract logs recorded so far.
+    fn clear_contract_logs(&mut self);
+    /// Whether contract logs are also forwarded to the `log` crate. Enabled by default.
+    fn set_log_passthrough(&mut self, enabled: bool);
 }
 
 impl SessionExt for PinkSession {
-    fn actor(&mut self) -> AccountId {
-        let actor = self.set_actor(AccountId::new(Default::default()));
-        self.set_actor(actor.clone());
-        actor
+    fn actor(&self) -> AccountId {
+        self.get_actor()
     }
     fn query<T>(&mut self, f: impl FnOnce() -> T) -> T {
+        let state = state::of(self);
         PinkRuntime::execute_in_mode(ExecMode::Query, || {
-            self.sandbox().dry_run(|sandbox| sandbox.execute_with(f))
+            state::using(state, || {
+                self.sandbox().dry_run(|sandbox| sandbox.execute_with(f))
+            })
         })
     }
     fn tx<T>(&mut self, f: impl FnOnce() -> T) -> T {
-        PinkRuntime::execute_in_mode(ExecMode::Transaction, || self.sandbox().execute_with(f))
+        let state = state::of(self);
+        state.lock().expect("Session state poisoned").denied_call = None;
+        let result = PinkRuntime::execute_in_mode(ExecMode::Transaction, || {
+            state::using(state.clone(), || self.sandbox().execute_with(f))
+        });
+        state
+            .lock()
+            .expect("Session state poisoned")
+            .apply_cache_ops();
+        result
+    }
+    fn estimate<T>(&mut self, f: impl FnOnce() -> T) -> T {
+        let state = state::of(self);
+        let checkpoint = state.lock().expect("Session state poisoned").checkpoint();
+        let result = PinkRuntime::execute_in_mode(ExecMode::Estimating, || {
+            state::using(state.clone(), || {
+                self.sandbox().dry_run(|sandbox| sandbox.execute_with(f))
+            })
+        });
+        // The transaction is executed again for real, only that run should be recorded.
+        state
+            .lock()
+            .expect("Session state poisoned")
+            .rollback(checkpoint);
+        result
+    }
+    fn as_actor<T>(&mut self, account: impl Into<AccountId>, f: impl FnOnce(&mut Self) -> T) -> T {
+        let mut session = SessionGuard::actor(self, account.into());
+        f(&mut *session)
+    }
+    fn fund(&mut self, account: impl Into<AccountId>, amount: Balance) {
+        let account = account.into();
+        self.tx(|| {
+            let _ = crate::runtime::Balances::deposit_creating(&account, amount);
+        })
+    }
+    fn transfer(
+        &mut self,
+        from: impl Into<AccountId>,
+        to: impl Into<AccountId>,
+        amount: Balance,
+    ) -> Result<()> {
+        let (from, to) = (from.into(), to.into());
+        self.tx(|| {
+            crate::runtime::Balances::transfer(&from, &to, amount, ExistenceRequirement::AllowDeath)
+                .map_err(|err| format!("FailedToTransfer: {err:?}").into())
+        })
+    }
+    fn free_balance(&mut self, account: impl Into<AccountId>) -> Balance {
+        let account = account.into();
+        self.query(|| crate::runtime::Balances::free_balance(&account))
     }
     fn set_driver<A: Encode>(&mut self, name: &str, contract: &A) -> Result<()> {
+        call_system(
+            self,
+            "set_driver",
+            (name, contract),
+            "FailedToCallSetDriver",
+        )
+    }
+    fn debug_messages(&mut self) -> Vec<String> {
+        state::with_state(self, |state| state.debug_messages.clone())
+    }
+    fn take_debug_messages(&mut self) -> Vec<String> {
+        state::with_state(self, |state| std::mem::take(&mut state.debug_messages))
+    }
+    fn contract_logs(&mut self) -> Vec<ContractLog> {
+        state::with_state(self, |state| state.logs.clone())
+    }
+    fn contract_logs_of<A: Encode>(&mut self, contract: &A) -> Vec<ContractLog> {
+        let contract = account_of(contract);
+        self.contract_logs()
+            .into_iter()
+            .filter(|log| log.contract == contract)
+            .collect()
+    }
+    fn clear_contract_logs(&mut self) {
+        state::with_state(self, |state| state.logs.clear());
+    }
+    fn set_log_passthrough(&mut self, enabled: bool) {
+        state::with_state(self, |state| state.quiet_logs = !enabled);
+    }
+}
+
+/// Blocks, time and snapshots of the chain of a session.
+pub trait ChainExt {
+    /// The number of the current block.
+    fn block_number(&mut self) -> BlockNumber;
+    /// Hash of the given block. Only blocks before the current one are known.
+    fn block_hash(&mut self, number: BlockNumber) -> H256;
+    /// Hash of the parent of the current block.
+    fn parent_hash(&mut self) -> H256;
+    /// The virtual timestamp in milliseconds.
+    fn timestamp(&mut self) -> u64;
+    /// Set the virtual timestamp in milliseconds.
+    fn set_timestamp(&mut self, millis: u64);
+    /// Build `n` blocks, returning the number of the current block afterwards.
+    fn advance_blocks(&mut self, n: u32) -> BlockNumber;
+    /// Build `n` blocks, advancing the virtual timestamp by `millis_per_block` per block.
+    fn advance_blocks_with_time(&mut self, n: u32, millis_per_block: u64) -> BlockNumber;
+    /// Register a hook invoked after each block built by `advance_blocks*`.
+    fn on_block(&mut self, hook: impl Fn(&mut Self, BlockNumber) + Send + Sync + 'static);
+    /// Capture the storage and off-chain state of the session.
+    fn snapshot(&mut self) -> Snapshot;
+    /// Replace the storage and off-chain state of the session with the snapshot.
+    fn restore(&mut self, snapshot: &Snapshot);
+    /// Save a snapshot of the session to a file.
+    fn save_state(&mut self, path: impl AsRef<Path>) -> Result<()>;
+    /// Replace the state of the session with a snapshot loaded from a file.
+    fn load_state(&mut self, path: impl AsRef<Path>) -> Result<()>;
+}
+
+impl ChainExt for PinkSession {
+    fn block_number(&mut self) -> BlockNumber {
+        self.query(crate::runtime::System::block_number)
+    }
+    fn block_hash(&mut self, number: BlockNumber) -> H256 {
+        self.query(|| crate::runtime::System::block_hash(number))
+    }
+    fn parent_hash(&mut self) -> H256 {
+        self.query(crate::runtime::System::parent_hash)
+    }
+    fn timestamp(&mut self) -> u64 {
+        self.query(crate::runtime::Timestamp::get)
+    }
+    fn set_timestamp(&mut self, millis: u64) {
+        self.tx(|| pallet_timestamp::Now::<PinkRuntime>::put(millis))
+    }
+    fn advance_blocks(&mut self, n: u32) -> BlockNumber {
+        self.advance_blocks_with_time(n, 0)
+    }
+    fn advance_blocks_with_time(&mut self, n: u32, millis_per_block: u64) -> BlockNumber {
+        let hooks = state::with_state(self, |state| state.block_hooks.clone());
+        for _ in 0..n {
+            let number = self.sandbox().build_block().expect("Failed to build block");
+            if millis_per_block > 0 {
+                self.tx(|| {
+                    pallet_timestamp::Now::<PinkRuntime>::mutate(|now| *now += millis_per_block)
+                });
+            }
+            for hook in &hooks {
+                hook(self, number);
+            }
+        }
+        self.block_number()
+    }
+    fn on_block(&mut self, hook: impl Fn(&mut Self, BlockNumber) + Send + Sync + 'static) {
+        state::with_state(self, |state| state.block_hooks.push(Arc::new(hook)));
+    }
+    fn snapshot(&mut self) -> Snapshot {
+        snapshot::take(self)
+    }
+    fn restore(&mut self, snapshot: &Snapshot) {
+        snapshot::restore(self, snapshot)
+    }
+    fn save_state(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        self.snapshot().save(path)
+    }
+    fn load_state(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let snapshot = Snapshot::load(path)?;
+        self.restore(&snapshot);
+        Ok(())
+    }
+}
+
+/// Raw access to the storage of contracts.
+pub trait StorageExt {
+    /// Install a contract from a state dump at the address it had in its original cluster.
+    fn import_contract(&mut self, dump: &ContractDump) -> Result<AccountId>;
+    /// Storage keys of a contract, as passed to `seal_set_storage` by the contract.
+    fn storage_keys<A: Encode>(&mut self, contract: &A) -> Result<Vec<Vec<u8>>>;
+    /// Read a raw storage entry of a contract.
+    fn read_storage<A: Encode>(&mut self, contract: &A, key: &[u8]) -> Result<Option<Vec<u8>>>;
+    /// Write a raw storage entry of a contract. Storage deposits are not charged.
+    fn write_storage<A: Encode>(&mut self, contract: &A, key: &[u8], value: &[u8]) -> Result<()>;
+    /// Remove a raw storage entry of a contract.
+    fn remove_storage<A: Encode>(&mut self, contract: &A, key: &[u8]) -> Result<()>;
+    /// Read and decode a storage entry, see [`ContractMetadata::key_of`] for the keys.
+    fn read_storage_as<A: Encode, T: Decode>(
+        &mut self,
+        contract: &A,
+        key: &[u8],
+    ) -> Result<Option<T>>;
+    /// Encode and write a storage entry, see [`ContractMetadata::key_of`] for the keys.
+    fn write_storage_as<A: Encode, T: Encode>(
+        &mut self,
+        contract: &A,
+        key: &[u8],
+        value: &T,
+    ) -> Result<()>;
+}
+
+impl StorageExt for PinkSession {
+    fn import_contract(&mut self, dump: &ContractDump) -> Result<AccountId> {
+        self.tx(|| import::install(dump))
+    }
+    fn storage_keys<A: Encode>(&mut self, contract: &A) -> Result<Vec<Vec<u8>>> {
+        let trie_id = contract_trie_id(self, contract)?;
+        Ok(self.query(|| {
+            storage::child_pairs(&trie_id)
+                .into_iter()
+                .filter_map(|(key, _)| storage::strip_contract_key(&key).map(<[u8]>::to_vec))
+                .collect()
+        }))
+    }
+    fn read_storage<A: Encode>(&mut self, contract: &A, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let trie_id = contract_trie_id(self, contract)?;
+        Ok(self.query(|| storage::get_child(&trie_id, &storage::contract_key(key))))
+    }
+    fn write_storage<A: Encode>(&mut self, contract: &A, key: &[u8], value: &[u8]) -> Result<()> {
+        let trie_id = contract_trie_id(self, contract)?;
+        self.tx(|| storage::put_child(&trie_id, &storage::contract_key(key), value));
+        Ok(())
+    }
+    fn remove_storage<A: Encode>(&mut self, contract: &A, key: &[u8]) -> Result<()> {
+        let trie_id = contract_trie_id(self, contract)?;
+        self.tx(|| storage::remove_child(&trie_id, &storage::contract_key(key)));
+        Ok(())
+    }
+    fn read_storage_as<A: Encode, T: Decode>(
+        &mut self,
+        contract: &A,
+        key: &[u8],
+    ) -> Result<Option<T>> {
+        self.read_storage(contract, key)?
+            .map(|value| {
+                T::decode(&mut &value[..]).map_err(|err| {
+                    format!("FailedToDecodeStorage(0x{}): {err:?}", hex::encode(key))
+                })
+            })
+            .transpose()
+            .map_err(Into::into)
+    }
+    fn write_storage_as<A: Encode, T: Encode>(
+        &mut self,
+        contract: &A,
+        key: &[u8],
+        value: &T,
+    ) -> Result<()> {
+        self.write_storage(contract, key, &value.encode())
+    }
+}
+
+/// Checks of the determinism of transactions.
+pub trait DeterminismExt {
+    /// Execute each transaction submitted through [`Callable`] twice on forked state with
+    /// different clock, randomness and HTTP responses first, failing it if the outcomes differ.
+    fn set_determinism_check(&mut self, enabled: bool);
+    /// Set the determinism transactions run with. `Determinism::Enforced` by default, like on
+    /// workers; `Determinism::Relaxed` allows calling code uploaded with relaxed determinism.
+    fn set_tx_determinism(&mut self, determinism: Determinism);
+    /// Set how nondeterministic extension calls made in transactions are handled.
+    fn set_nondeterminism_policy(&mut self, policy: NondeterminismPolicy);
+    /// Nondeterministic extension calls recorded under the `Warn` or `Deny` policy so far.
+    fn nondeterministic_calls(&mut self) -> Vec<NondeterministicCall>;
+}
+
+impl DeterminismExt for PinkSession {
+    fn set_determinism_check(&mut self, enabled: bool) {
+        state::with_state(self, |state| state.determinism_check = enabled);
+    }
+    fn set_tx_determinism(&mut self, determinism: Determinism) {
+        state::with_state(self, |state| state.tx_determinism = Some(determinism));
+    }
+    fn set_nondeterminism_policy(&mut self, policy: NondeterminismPolicy) {
+        state::with_state(self, |state| state.nondeterminism_policy = policy);
+    }
+    fn nondeterministic_calls(&mut self) -> Vec<NondeterministicCall> {
+        state::with_state(self, |state| state.nondeterministic_calls.clone())
+    }
+}
+
+/// Traces of the calls made in a session.
+pub trait TracingExt {
+    /// Record a [`CallTrace`] of every call and instantiation from now on.
+    fn set_tracing(&mut self, enabled: bool);
+    /// Traces recorded so far, outermost calls in execution order.
+    fn traces(&mut self) -> Vec<CallTrace>;
+    /// Return and clear the traces recorded so far.
+    fn take_traces(&mut self) -> Vec<CallTrace>;
+    /// Use `metadata` to decode the input and output of calls to `contract` in traces.
+    fn register_metadata<A: Encode>(&mut self, contract: &A, metadata: ContractMetadata);
+}
+
+impl TracingExt for PinkSession {
+    fn set_tracing(&mut self, enabled: bool) {
+        state::with_state(self, |state| state.tracing = enabled);
+    }
+    fn traces(&mut self) -> Vec<CallTrace> {
+        state::with_state(self, |state| state.traces.clone())
+    }
+    fn take_traces(&mut self) -> Vec<CallTrace> {
+        state::with_state(self, |state| std::mem::take(&mut state.traces))
+    }
+    fn register_metadata<A: Encode>(&mut self, contract: &A, metadata: ContractMetadata) {
+        state::with_state(self, |state| {
+            state
+                .metadata
+                .insert(account_of(contract), Arc::new(metadata))
+        });
+    }
+}
+
+/// Mocks and faults of the chain extension.
+pub trait MockExt {
+    /// Answer every call to `function` with `f` instead of the simulated worker.
+    ///
+    /// `f` receives the tuple of the method arguments, e.g. `(AccountId,)` for `balance_of`,
+    /// and returns the value the method returns on success.
+    fn mock_ext<Args: Decode, Ret: Encode>(
+        &mut self,
+        function: ExtFunction,
+        f: impl Fn(Args) -> Ret + Send + Sync + 'static,
+    );
+    /// Like [`mock_ext`](Self::mock_ext), but only for calls made by `contract`.
+    fn mock_ext_for<A: Encode, Args: Decode, Ret: Encode>(
+        &mut self,
+        contract: &A,
+        function: ExtFunction,
+        f: impl Fn(Args) -> Ret + Send + Sync + 'static,
+    );
+    /// Remove the session wide mock of `function`.
+    fn unmock_ext(&mut self, function: ExtFunction);
+    /// Remove the mock of `function` scoped to `contract` by [`mock_ext_for`](Self::mock_ext_for).
+    fn unmock_ext_for<A: Encode>(&mut self, contract: &A, function: ExtFunction);
+    /// Remove every session wide and contract scoped mock.
+    fn clear_ext_mocks(&mut self);
+    /// Make matching chain extension calls fail, see [`FaultInjection`].
+    fn inject_fault(&mut self, injection: FaultInjection);
+    /// Remove every injected fault, hit or not.
+    fn clear_faults(&mut self);
+}
+
+impl MockExt for PinkSession {
+    fn mock_ext<Args: Decode, Ret: Encode>(
+        &mut self,
+        function: ExtFunction,
+        f: impl Fn(Args) -> Ret + Send + Sync + 'static,
+    ) {
+        state::with_state(self, |state| {
+            state.ext_mocks.insert(function, None, ext::handler(f))
+        });
+    }
+    fn mock_ext_for<A: Encode, Args: Decode, Ret: Encode>(
+        &mut self,
+        contract: &A,
+        function: ExtFunction,
+        f: impl Fn(Args) -> Ret + Send + Sync + 'static,
+    ) {
+        state::with_state(self, |state| {
+            state
+                .ext_mocks
+                .insert(function, Some(account_of(contract)), ext::handler(f))
+        });
+    }
+    fn unmock_ext(&mut self, function: ExtFunction) {
+        state::with_state(self, |state| state.ext_mocks.remove(function, None));
+    }
+    fn unmock_ext_for<A: Encode>(&mut self, contract: &A, function: ExtFunction) {
+        let contract = account_of(contract);
+        state::with_state(self, |state| {
+            state.ext_mocks.remove(function, Some(contract))
+        });
+    }
+    fn clear_ext_mocks(&mut self) {
+        state::with_state(self, |state| state.ext_mocks.clear());
+    }
+    fn inject_fault(&mut self, injection: FaultInjection) {
+        state::with_state(self, |state| state.faults.inject(injection));
+    }
+    fn clear_faults(&mut self) {
+        state::with_state(self, |state| state.faults.clear());
+    }
+}
+
+/// The simulated workers of the cluster and the calls reaching them from clients.
+pub trait WorkerExt {
+    /// Set the keys and SGX quote of the active worker.
+    fn set_worker_identity(&mut self, identity: WorkerIdentity);
+    /// The keys and SGX quote of the active worker, if set.
+    fn worker_identity(&mut self) -> Option<WorkerIdentity>;
+    /// Add a simulated worker to the cluster. It has its own identity, cache, clock, randomness
+    /// and extension mocks, and shares the on-chain state with the other workers.
+    fn add_worker(&mut self, identity: WorkerIdentity) -> WorkerId;
+    /// Make `worker` serve the following queries. Worker `0` serves them initially.
+    fn select_worker(&mut self, worker: WorkerId) -> Result<()>;
+    /// The worker serving queries.
+    fn active_worker(&mut self) -> WorkerId;
+    /// Run `f` with `worker` serving the queries, switching back to the active worker afterwards.
+    fn with_worker<T>(&mut self, worker: WorkerId, f: impl FnOnce(&mut Self) -> T) -> Result<T>;
+    /// Like [`mock_ext`](MockExt::mock_ext), but only on the active worker.
+    fn mock_worker_ext<Args: Decode, Ret: Encode>(
+        &mut self,
+        function: ExtFunction,
+        f: impl Fn(Args) -> Ret + Send + Sync + 'static,
+    );
+    /// Freeze the clock of the active worker at `millis`, or follow the system clock if `None`.
+    fn set_offchain_clock(&mut self, millis: Option<u64>);
+    /// Make `getrandom` on the active worker deterministic, or use the OS randomness if `None`.
+    fn set_random_seed(&mut self, seed: Option<[u8; 32]>);
+    /// Verify a signed query like the active worker does and run it with the root of its
+    /// certificate chain as the caller, see [`QueryEnvelope`].
+    fn query_envelope<Ret: Decode>(&mut self, envelope: &QueryEnvelope) -> Result<Ret>;
+    /// Decrypt a query with the ECDH key of the active worker, run it as the session actor and
+    /// return the output encrypted back to the sender.
+    fn query_encrypted<A: Encode>(
+        &mut self,
+        contract: &A,
+        request: &EncryptedData,
+    ) -> Result<EncryptedData>;
+    /// Like [`query_encrypted`](Self::query_encrypted), but submitting the call as a transaction.
+    fn submit_tx_encrypted<A: Encode>(
+        &mut self,
+        contract: &A,
+        request: &EncryptedData,
+    ) -> Result<EncryptedData>;
+}
+
+impl WorkerExt for PinkSession {
+    fn set_worker_identity(&mut self, identity: WorkerIdentity) {
+        state::with_state(self, |state| state.worker.identity = Some(identity));
+    }
+    fn worker_identity(&mut self) -> Option<WorkerIdentity> {
+        state::with_state(self, |state| state.worker.identity.clone())
+    }
+    fn add_worker(&mut self, identity: WorkerIdentity) -> WorkerId {
+        state::with_state(self, |state| state.add_worker(identity))
+    }
+    fn select_worker(&mut self, worker: WorkerId) -> Result<()> {
+        state::with_state(self, |state| state.switch_worker(worker)).map_err(Into::into)
+    }
+    fn active_worker(&mut self) -> WorkerId {
+        state::with_state(self, |state| state.active_worker)
+    }
+    fn with_worker<T>(&mut self, worker: WorkerId, f: impl FnOnce(&mut Self) -> T) -> Result<T> {
+        let previous = self.active_worker();
+        self.select_worker(worker)?;
+        let mut session = SessionGuard::new(self, move |session| {
+            if let Err(err) = session.select_worker(previous) {
+                log::error!("Failed to switch back to worker {previous}: {err}");
+            }
+        });
+        Ok(f(&mut *session))
+    }
+    fn mock_worker_ext<Args: Decode, Ret: Encode>(
+        &mut self,
+        function: ExtFunction,
+        f: impl Fn(Args) -> Ret + Send + Sync + 'static,
+    ) {
+        state::with_state(self, |state| {
+            state
+                .worker
+                .ext_mocks
+                .insert(function, None, ext::handler(f))
+        });
+    }
+    fn set_offchain_clock(&mut self, millis: Option<u64>) {
+        state::with_state(self, |state| state.worker.offchain.clock = millis);
+    }
+    fn set_random_seed(&mut self, seed: Option<[u8; 32]>) {
+        state::with_state(self, |state| {
+            state.worker.offchain.rng = seed.map(SeededRng::new)
+        });
+    }
+    fn query_envelope<Ret: Decode>(&mut self, envelope: &QueryEnvelope) -> Result<Ret> {
+        let block_number = self.block_number();
+        let (origin, query) = envelope.verify(block_number)?;
+        let nonce = query.head.nonce;
+        if !state::with_state(self, |state| state.worker.query_nonces.insert(nonce)) {
+            return Err(format!("DuplicateQueryNonce: 0x{}", hex::encode(nonce)).into());
+        }
+        let request = CallRequest {
+            dest: query.head.id,
+            value: 0,
+            gas_limit: 0,
+            data: query.data,
+        };
+        let result = self.query(|| request.call(origin, DEFAULT_QUERY_GAS_LIMIT, None, false));
+        decode_call_result(self, result).map(|result| result.value)
+    }
+    fn query_encrypted<A: Encode>(
+        &mut self,
+        contract: &A,
+        request: &EncryptedData,
+    ) -> Result<EncryptedData> {
+        let worker = worker_ecdh_pair(self)?;
+        let call = CallRequest {
+            dest: account_of(contract),
+            value: 0,
+            gas_limit: 0,
+            data: request.open(&worker)?,
+        };
+        let actor = self.actor();
+        let result = self.query(|| call.call(actor, DEFAULT_QUERY_GAS_LIMIT, None, false));
+        seal_output(&worker, &request.pubkey, result)
+    }
+    fn submit_tx_encrypted<A: Encode>(
+        &mut self,
+        contract: &A,
+        request: &EncryptedData,
+    ) -> Result<EncryptedData> {
+        let worker = worker_ecdh_pair(self)?;
+        let call = CallRequest {
+            dest: account_of(contract),
+            value: 0,
+            gas_limit: 0,
+            data: request.open(&worker)?,
+        };
+        let determinism = tx_determinism(self);
+        let result = execute_tx(call, self, determinism)?;
+        seal_output(&worker, &request.pubkey, result)
+    }
+}
+
+/// Clusters, their owner and the privileged operations in them.
+pub trait ClusterExt {
+    /// Create an empty cluster with its own system contract, key and config. Contracts deployed
+    /// in it get different addresses than in the other clusters.
+    fn create_cluster(&mut self, cluster_id: ClusterId) -> Result<()>;
+    /// Like [`create_cluster`](Self::create_cluster), owned by `owner`.
+    fn create_cluster_with_owner(&mut self, cluster_id: ClusterId, owner: AccountId) -> Result<()>;
+    /// Make `cluster_id` receive the following calls. The cluster with id zero, created along
+    /// with the session, receives them initially. Block number, timestamp and balances are shared
+    /// by all clusters.
+    fn select_cluster(&mut self, cluster_id: ClusterId) -> Result<()>;
+    /// The cluster receiving calls.
+    fn active_cluster(&mut self) -> ClusterId;
+    /// Run `f` against `cluster_id`, switching back to the active cluster afterwards.
+    fn with_cluster<T>(
+        &mut self,
+        cluster_id: ClusterId,
+        f: impl FnOnce(&mut Self) -> T,
+    ) -> Result<T>;
+    /// The owner of the active cluster, the default actor unless configured otherwise.
+    fn cluster_owner(&mut self) -> Result<AccountId>;
+    /// Run `f` with the cluster owner as the actor, restoring the actor afterwards.
+    fn as_cluster_owner<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> Result<T>;
+    /// Make `contract` an admin of the cluster. Only the cluster owner may do this.
+    fn grant_admin<A: Encode>(&mut self, contract: &A) -> Result<()>;
+    /// Set the gas price and storage deposits of the cluster. Only the cluster owner may do this.
+    fn set_cluster_prices(
+        &mut self,
+        gas_price: Balance,
+        deposit_per_item: Balance,
+        deposit_per_byte: Balance,
+    ) -> Result<()>;
+    /// Privileged operations attempted without the required role, in execution order.
+    fn permission_denials(&mut self) -> Vec<PermissionDenial>;
+    /// Return and clear the permission denials recorded so far.
+    fn take_permission_denials(&mut self) -> Vec<PermissionDenial>;
+    /// Event blocks produced by the transactions of the active cluster, oldest first.
+    fn event_blocks(&mut self) -> Vec<EventBlock>;
+    /// The number of the next event block and the hash of the last one, as returned by
+    /// `current_event_chain_head`.
+    fn event_chain_head(&mut self) -> (u64, H256);
+}
+
+impl ClusterExt for PinkSession {
+    fn create_cluster(&mut self, cluster_id: ClusterId) -> Result<()> {
+        cluster::create(self, cluster_id, PinkRuntime::default_actor())
+    }
+    fn create_cluster_with_owner(&mut self, cluster_id: ClusterId, owner: AccountId) -> Result<()> {
+        cluster::create(self, cluster_id, owner)
+    }
+    fn select_cluster(&mut self, cluster_id: ClusterId) -> Result<()> {
+        cluster::switch(self, cluster_id)
+    }
+    fn active_cluster(&mut self) -> ClusterId {
+        state::with_state(self, |state| state.active_cluster)
+    }
+    fn with_cluster<T>(
+        &mut self,
+        cluster_id: ClusterId,
+        f: impl FnOnce(&mut Self) -> T,
+    ) -> Result<T> {
+        let previous = self.active_cluster();
+        self.select_cluster(cluster_id)?;
+        let mut session = SessionGuard::new(self, move |session| {
+            if let Err(err) = session.select_cluster(previous) {
+                log::error!("Failed to switch back to cluster {previous:?}: {err}");
+            }
+        });
+        Ok(f(&mut *session))
+    }
+    fn cluster_owner(&mut self) -> Result<AccountId> {
+        self.query(crate::runtime::Pink::cluster_owner)
+            .ok_or_else(|| "ClusterOwnerNotFound".into())
+    }
+    fn as_cluster_owner<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> Result<T> {
+        let owner = self.cluster_owner()?;
+        Ok(self.as_actor(owner, f))
+    }
+    fn grant_admin<A: Encode>(&mut self, contract: &A) -> Result<()> {
+        call_system(self, "grant_admin", contract, "FailedToCallGrantAdmin")
+    }
+    fn set_cluster_prices(
+        &mut self,
+        gas_price: Balance,
+        deposit_per_item: Balance,
+        deposit_per_byte: Balance,
+    ) -> Result<()> {
         let caller = self.actor();
         self.tx(|| {
-            let system_address =
-                crate::runtime::Pink::system_contract().expect("System contract not found");
-            let selector_set_driver = 0xaa1e2030u32.to_be_bytes();
-            let input_data = (selector_set_driver, name, contract).encode();
-            PinkRuntime::call(
-                caller,
-                system_address.clone(),
-                0,
-                u64::MAX,
-                None,
-                input_data,
-                true,
+            PinkRuntime::set_cluster_prices(&caller, gas_price, deposit_per_item, deposit_per_byte)
+        })
+    }
+    fn permission_denials(&mut self) -> Vec<PermissionDenial> {
+        state::with_state(self, |state| state.permission_denials.clone())
+    }
+    fn take_permission_denials(&mut self) -> Vec<PermissionDenial> {
+        state::with_state(self, |state| std::mem::take(&mut state.permission_denials))
+    }
+    fn event_blocks(&mut self) -> Vec<EventBlock> {
+        self.query(event_chain::blocks)
+    }
+    fn event_chain_head(&mut self) -> (u64, H256) {
+        self.query(|| {
+            (
+                crate::runtime::Pink::next_event_block_number(),
+                crate::runtime::Pink::last_event_block_hash(),
             )
-            .map(|_| ())
-            .map_err(|err| format!("FailedToCallSetDriver: {err:?}").into())
         })
     }
 }
 
+/// Gives access to a session and restores what the code using it changed when dropped, even if
+/// that code panics.
+struct SessionGuard<'a> {
+    session: &'a mut PinkSession,
+    restore: Option<Box<dyn FnOnce(&mut PinkSession) + 'a>>,
+}
+
+impl<'a> SessionGuard<'a> {
+    fn new(session: &'a mut PinkSession, restore: impl FnOnce(&mut PinkSession) + 'a) -> Self {
+        Self {
+            session,
+            restore: Some(Box::new(restore)),
+        }
+    }
+
+    /// Make `actor` the actor of the session until the guard is dropped.
+    fn actor(session: &'a mut PinkSession, actor: AccountId) -> Self {
+        let previous = session.set_actor(actor);
+        Self::new(session, move |session| {
+            session.set_actor(previous);
+        })
+    }
+}
+
+impl std::ops::Deref for SessionGuard<'_> {
+    type Target = PinkSession;
+
+    fn deref(&self) -> &PinkSession {
+        self.session
+    }
+}
+
+impl std::ops::DerefMut for SessionGuard<'_> {
+    fn deref_mut(&mut self) -> &mut PinkSession {
+        self.session
+    }
+}
+
+impl Drop for SessionGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(restore) = self.restore.take() {
+            restore(self.session);
+        }
+    }
+}
+
+/// Fail if the last transaction was stopped by the `Deny` nondeterminism policy.
+fn ensure_not_denied(session: &mut PinkSession) -> Result<()> {
+    let denied = state::with_state(session, |state| state.denied_call.take());
+    match denied {
+        Some(call) => Err(format!("NondeterministicExtCall: {call}").into()),
+        None => Ok(()),
+    }
+}
+
+/// The permission denial explaining the failure of the latest call, if any.
+fn call_denial(session: &mut PinkSession) -> Option<PermissionDenial> {
+    state::with_state(session, |state| state.call_denial.clone())
+}
+
+/// Call a message of the system contract returning `Result<(), pink::system::Error>` as the
+/// session actor.
+fn call_system<Args: Encode>(
+    session: &mut PinkSession,
+    message: &str,
+    args: Args,
+    error: &str,
+) -> Result<()> {
+    let caller = session.actor();
+    let input_data = (permission::system_selector(message), args).encode();
+    let result = session.tx(|| {
+        let system_address =
+            crate::runtime::Pink::system_contract().expect("System contract not found");
+        PinkRuntime::bare_call(caller, system_address, 0, u64::MAX, None, input_data, true)
+    });
+    let output = result
+        .result
+        .map_err(|err| format!("{error}: {}", describe_error(&err)))?;
+    type SystemResult = core::result::Result<(), pink::system::Error>;
+    match MessageResult::<SystemResult>::decode(&mut &output.data[..]) {
+        Ok(Ok(Ok(()))) => Ok(()),
+        Ok(Ok(Err(err))) => Err(call_denial(session)
+            .map_or_else(|| format!("{error}: {err:?}"), |denial| denial.to_string())
+            .into()),
+        _ => Err(format!("{error}: unexpected output 0x{}", hex::encode(&output.data)).into()),
+    }
+}
+
+/// The ECDH key of the active worker, which encrypted calls are addressed to.
+fn worker_ecdh_pair(session: &mut PinkSession) -> Result<sr25519::Pair> {
+    session
+        .worker_identity()
+        .map(|identity| identity.ecdh_pair().clone())
+        .ok_or_else(|| {
+            "NoWorkerIdentity: encrypted calls need a worker identity, see `set_worker_identity`"
+                .into()
+        })
+}
+
+fn seal_output(
+    worker: &sr25519::Pair,
+    peer: &EcdhPublicKey,
+    result: ContractExecResult,
+) -> Result<EncryptedData> {
+    let output = result
+        .result
+        .map_err(|err| format!("Failed to execute call: {}", describe_error(&err)))?
+        .data;
+    EncryptedData::seal(worker, peer, &output)
+}
+
+fn contract_trie_id<A: Encode>(session: &mut PinkSession, contract: &A) -> Result<Vec<u8>> {
+    let contract = account_of(contract);
+    session
+        .query(|| storage::contract_trie_id(&contract))
+        .ok_or_else(|| format!("ContractNotFound: {contract}").into())
+}
+
+fn account_of<A: Encode>(contract: &A) -> AccountId {
+    Decode::decode(&mut &contract.encode()[..]).expect("Failed to decode account id")
+}
+
 pub trait DeployBundle {
     type Contract;
     fn deploy_bundle(
@@ -95,15 +879,100 @@ pub trait DeployBundle {
 pub trait Deployable {
     type Contract;
     fn deploy(self, session: &mut PinkSession) -> Result<Self::Contract>;
+    /// Dry-run the instantiation first and deploy with the estimated gas and storage deposit
+    /// limits, raised by `margin_percent`. Fails without deploying if the dry-run reverts.
+    fn deploy_estimated(
+        self,
+        session: &mut PinkSession,
+        margin_percent: u32,
+    ) -> Result<Self::Contract>;
     fn bare_deploy(self, session: &mut PinkSession) -> ContractInstantiateResult;
 }
 
+/// The decoded return value of a call together with the execution details reported by
+/// `pallet_contracts`.
+#[derive(Debug, Clone)]
+pub struct CallResult<T> {
+    pub value: T,
+    pub gas_consumed: Weight,
+    pub gas_required: Weight,
+    pub storage_deposit: StorageDeposit<Balance>,
+    pub debug_message: String,
+}
+
+impl<T> CallResult<T> {
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> CallResult<U> {
+        CallResult {
+            value: f(self.value),
+            gas_consumed: self.gas_consumed,
+            gas_required: self.gas_required,
+            storage_deposit: self.storage_deposit,
+            debug_message: self.debug_message,
+        }
+    }
+}
+
 pub trait Callable {
     type Ret;
     fn submit_tx(self, session: &mut PinkSession) -> Result<Self::Ret>;
+    fn submit_tx_detailed(self, session: &mut PinkSession) -> Result<CallResult<Self::Ret>>;
+    /// Dry-run the transaction first and submit it with the estimated gas and storage deposit
+    /// limits, raised by `margin_percent`. If the dry-run reverts, the transaction is not
+    /// submitted and the decoded revert is returned instead.
+    fn submit_tx_estimated(
+        self,
+        session: &mut PinkSession,
+        margin_percent: u32,
+    ) -> Result<Self::Ret>;
+    /// Submit a transaction with the given determinism instead of the one of the session.
+    fn submit_tx_with_determinism(
+        self,
+        session: &mut PinkSession,
+        determinism: Determinism,
+    ) -> Result<Self::Ret>;
     fn bare_tx(self, session: &mut PinkSession) -> ContractExecResult;
     fn query(self, session: &mut PinkSession) -> Result<Self::Ret>;
+    fn query_detailed(self, session: &mut PinkSession) -> Result<CallResult<Self::Ret>>;
     fn bare_query(self, session: &mut PinkSession) -> ContractExecResult;
+    /// Build a query envelope signed by `signer`, see [`WorkerExt::query_envelope`].
+    fn envelope(self, signer: &dyn EnvelopeSigner) -> QueryEnvelope;
+    /// Query through the encrypted channel of the active worker, with `client` as the ECDH key
+    /// of the caller.
+    fn encrypted_query(
+        self,
+        session: &mut PinkSession,
+        client: &sr25519::Pair,
+    ) -> Result<Self::Ret>;
+    /// Submit a transaction through the encrypted channel of the active worker.
+    fn submit_encrypted_tx(
+        self,
+        session: &mut PinkSession,
+        client: &sr25519::Pair,
+    ) -> Result<Self::Ret>;
+    /// Query as `signer`, authenticated by a signed envelope instead of the session actor.
+    fn query_as_signer(
+        self,
+        session: &mut PinkSession,
+        signer: &dyn EnvelopeSigner,
+    ) -> Result<Self::Ret>;
+
+    /// Submit a transaction to a message returning `Result<T, E>`, surfacing the contract's
+    /// own error as `CallError::Contract`.
+    fn try_submit_tx<T, E>(self, session: &mut PinkSession) -> Result<T, CallError<E>>
+    where
+        Self: Sized + Callable<Ret = core::result::Result<T, E>>,
+    {
+        self.submit_tx(session)?.map_err(CallError::Contract)
+    }
+
+    /// Query a message returning `Result<T, E>`, surfacing the contract's own error as
+    /// `CallError::Contract`.
+    fn try_query<T, E>(self, session: &mut PinkSession) -> Result<T, CallError<E>>
+    where
+        Self: Sized + Callable<Ret = core::result::Result<T, E>>,
+    {
+        self.query(session)?.map_err(CallError::Contract)
+    }
 }
 
 impl<Env, Contract, Args, Salt> DeployBundle
@@ -126,7 +995,9 @@ where
     type Contract = Contract;
 
     fn deploy_wasm(self, wasm: &[u8], session: &mut PinkSession) -> Result<Self::Contract> {
-        into_contract(self.bare_deploy(wasm, session)?)
+        let result = self.bare_deploy(wasm, session)?;
+        ensure_not_denied(session)?;
+        into_contract(result)
     }
 
     fn bare_deploy(
@@ -190,27 +1061,121 @@ where
     type Contract = Contract;
 
     fn deploy(self, session: &mut PinkSession) -> Result<Self::Contract> {
-        into_contract(self.bare_deploy(session))
+        let result = self.bare_deploy(session);
+        ensure_not_denied(session)?;
+        into_contract(result)
+    }
+    fn deploy_estimated(
+        self,
+        session: &mut PinkSession,
+        margin_percent: u32,
+    ) -> Result<Self::Contract> {
+        let caller = session.actor();
+        let request = InstantiateRequest::new(self);
+        let estimation =
+            session.estimate(|| request.instantiate(caller.clone(), DEFAULT_QUERY_GAS_LIMIT, None));
+        match &estimation.result {
+            Err(err) => return Err(format!("Failed to estimate gas: {err:?}").into()),
+            Ok(v) if v.result.did_revert() => {
+                return Err(format!(
+                    "Failed to estimate gas: contract instantiation reverted: 0x{}",
+                    hex::encode(&v.result.data)
+                )
+                .into())
+            }
+            Ok(_) => {}
+        }
+        let limits = EstimatedLimits::new(
+            estimation.gas_required,
+            &estimation.storage_deposit,
+            margin_percent,
+        );
+        let result = session.tx(|| {
+            request.instantiate(caller, limits.gas_limit, Some(limits.storage_deposit_limit))
+        });
+        ensure_not_denied(session)?;
+        into_contract(result)
     }
     fn bare_deploy(self, session: &mut PinkSession) -> ContractInstantiateResult {
         let caller = session.actor();
-        let constructor = self.endowment(0).gas_limit(DEFAULT_TX_GAS_LIMIT);
+        let request = InstantiateRequest::new(self);
+        session.tx(|| request.instantiate(caller, request.gas_limit, None))
+    }
+}
+
+struct InstantiateRequest {
+    code_hash: sp_core::H256,
+    gas_limit: u64,
+    data: Vec<u8>,
+    salt: Vec<u8>,
+}
+
+impl InstantiateRequest {
+    fn new<Env, Contract, Args, Salt>(
+        builder: CreateBuilder<
+            Env,
+            Contract,
+            Set<Hash>,
+            Unset<u64>,
+            Unset<Balance>,
+            Set<ExecutionInput<Args>>,
+            Set<Salt>,
+            Set<ReturnType<Contract>>,
+        >,
+    ) -> Self
+    where
+        Env: Environment<Hash = Hash, Balance = Balance>,
+        Contract: FromAccountId<Env>,
+        Args: Encode,
+        Salt: AsRef<[u8]>,
+    {
+        let constructor = builder.endowment(0).gas_limit(DEFAULT_TX_GAS_LIMIT);
         let params = constructor.params();
         let code_hash: &[u8] = params.code_hash().as_ref();
-        let code_hash = sp_core::H256(code_hash.try_into().expect("Hash convert failed"));
-        let input_data = params.exec_input().encode();
-
-        session.tx(|| {
-            PinkRuntime::bare_instantiate(
-                caller,
-                0,
-                params.gas_limit(),
-                None,
-                code_hash.into(),
-                input_data,
-                params.salt_bytes().as_ref().to_vec(),
-            )
-        })
+        Self {
+            code_hash: sp_core::H256(code_hash.try_into().expect("Hash convert failed")),
+            gas_limit: params.gas_limit(),
+            data: params.exec_input().encode(),
+            salt: params.salt_bytes().as_ref().to_vec(),
+        }
+    }
+
+    fn instantiate(
+        &self,
+        caller: AccountId,
+        gas_limit: u64,
+        storage_deposit_limit: Option<Balance>,
+    ) -> ContractInstantiateResult {
+        PinkRuntime::bare_instantiate(
+            caller,
+            0,
+            gas_limit,
+            storage_deposit_limit,
+            self.code_hash.into(),
+            self.data.clone(),
+            self.salt.clone(),
+        )
+    }
+}
+
+/// Limits derived from a dry-run, the way Phala clients submit transactions.
+struct EstimatedLimits {
+    gas_limit: u64,
+    storage_deposit_limit: Balance,
+}
+
+impl EstimatedLimits {
+    fn new(
+        gas_required: Weight,
+        storage_deposit: &StorageDeposit<Balance>,
+        margin_percent: u32,
+    ) -> Self {
+        let margin = |value: u128| value.saturating_mul(100 + margin_percent as u128) / 100;
+        let gas_limit = margin(gas_required.ref_time() as u128).min(u64::MAX as u128) as u64;
+        Self {
+            gas_limit,
+            storage_deposit_limit: margin(storage_deposit.charge_or_zero()),
+        }
     }
 }
 
@@ -227,7 +1192,7 @@ where
                 v.account_id
             }
         }
-        Err(err) => return Err(format!("{err:?}").into()),
+        Err(err) => return Err(describe_error(&err).into()),
     };
     let account_id =
         Decode::decode(&mut &account_id.encode()[..]).expect("Failed to decode account id");
@@ -244,41 +1209,198 @@ where
     type Ret = Ret;
 
     fn submit_tx(self, session: &mut PinkSession) -> Result<Self::Ret> {
+        self.submit_tx_detailed(session).map(|result| result.value)
+    }
+    fn submit_tx_detailed(self, session: &mut PinkSession) -> Result<CallResult<Self::Ret>> {
+        let determinism = tx_determinism(session);
+        submit_tx(CallRequest::new(self), session, determinism)
+    }
+    fn submit_tx_with_determinism(
+        self,
+        session: &mut PinkSession,
+        determinism: Determinism,
+    ) -> Result<Self::Ret> {
+        submit_tx(CallRequest::new(self), session, determinism).map(|result| result.value)
+    }
+    fn submit_tx_estimated(self, session: &mut PinkSession, margin_percent: u32) -> Result<Ret> {
         let actor = session.actor();
-        session.tx(move || call(self, true, actor))
+        let deterministic = tx_determinism(session) == Determinism::Enforced;
+        let request = CallRequest::new(self);
+        let estimation = session
+            .estimate(|| request.call(actor.clone(), DEFAULT_QUERY_GAS_LIMIT, None, deterministic));
+        match &estimation.result {
+            Err(err) => {
+                return Err(format!("Failed to estimate gas: {}", describe_error(err)).into())
+            }
+            // Submitting would revert the same way.
+            Ok(v) if v.did_revert() => {
+                return decode_call_result(session, estimation).map(|r| r.value)
+            }
+            Ok(_) => {}
+        }
+        let limits = EstimatedLimits::new(
+            estimation.gas_required,
+            &estimation.storage_deposit,
+            margin_percent,
+        );
+        let storage_deposit_limit = Some(limits.storage_deposit_limit);
+        determinism::check(session, || {
+            request.call(
+                actor.clone(),
+                limits.gas_limit,
+                storage_deposit_limit,
+                deterministic,
+            )
+        })?;
+        let result = session.tx(|| {
+            request.call(
+                actor,
+                limits.gas_limit,
+                storage_deposit_limit,
+                deterministic,
+            )
+        });
+        ensure_not_denied(session)?;
+        decode_call_result(session, result).map(|result| result.value)
     }
     fn bare_tx(self, session: &mut PinkSession) -> ContractExecResult {
         let actor = session.actor();
-        session.tx(move || bare_call(self, false, actor))
+        let deterministic = tx_determinism(session) == Determinism::Enforced;
+        let request = CallRequest::new(self);
+        let gas_limit = request.gas_limit_or(DEFAULT_TX_GAS_LIMIT);
+        session.tx(|| request.call(actor, gas_limit, None, deterministic))
     }
     fn query(self, session: &mut PinkSession) -> Result<Self::Ret> {
         let actor = session.actor();
-        session.query(move || call(self, false, actor))
+        let result = session.query(move || bare_call(self, false, actor));
+        decode_call_result(session, result).map(|result| result.value)
+    }
+    fn query_detailed(self, session: &mut PinkSession) -> Result<CallResult<Self::Ret>> {
+        let actor = session.actor();
+        let result = session.query(move || bare_call(self, false, actor));
+        decode_call_result(session, result)
     }
     fn bare_query(self, session: &mut PinkSession) -> ContractExecResult {
         let actor = session.actor();
         session.query(move || bare_call(self, false, actor))
     }
+    fn envelope(self, signer: &dyn EnvelopeSigner) -> QueryEnvelope {
+        let request = CallRequest::new(self);
+        QueryEnvelope::new(request.dest, request.data, signer)
+    }
+    fn query_as_signer(
+        self,
+        session: &mut PinkSession,
+        signer: &dyn EnvelopeSigner,
+    ) -> Result<Ret> {
+        session.query_envelope(&self.envelope(signer))
+    }
+    fn encrypted_query(self, session: &mut PinkSession, client: &sr25519::Pair) -> Result<Ret> {
+        let request = CallRequest::new(self);
+        let sealed = seal_input(session, client, &request.data)?;
+        let response = session.query_encrypted(&request.dest, &sealed)?;
+        decode_output(&response.open(client)?)
+    }
+    fn submit_encrypted_tx(self, session: &mut PinkSession, client: &sr25519::Pair) -> Result<Ret> {
+        let request = CallRequest::new(self);
+        let sealed = seal_input(session, client, &request.data)?;
+        let response = session.submit_tx_encrypted(&request.dest, &sealed)?;
+        decode_output(&response.open(client)?)
+    }
 }
 
-fn call<Env, Args, Ret>(
-    call_builder: CallBuilder<Env, Set<Call<Env>>, Set<ExecutionInput<Args>>, Set<ReturnType<Ret>>>,
-    deterministic: bool,
-    actor: AccountId,
-) -> Result<Ret>
-where
-    Env: Environment<Balance = Balance>,
-    Args: Encode,
-    Ret: Decode,
-{
-    let result = bare_call(call_builder, deterministic, actor);
-    let result = result
-        .result
-        .map_err(|e| format!("Failed to execute call: {e:?}"))?;
-    let ret = MessageResult::<Ret>::decode(&mut &result.data[..])
+fn submit_tx<Ret: Decode>(
+    request: CallRequest,
+    session: &mut PinkSession,
+    determinism: Determinism,
+) -> Result<CallResult<Ret>> {
+    let result = execute_tx(request, session, determinism)?;
+    decode_call_result(session, result)
+}
+
+fn execute_tx(
+    request: CallRequest,
+    session: &mut PinkSession,
+    determinism: Determinism,
+) -> Result<ContractExecResult> {
+    let actor = session.actor();
+    let deterministic = determinism == Determinism::Enforced;
+    let gas_limit = request.gas_limit_or(DEFAULT_TX_GAS_LIMIT);
+    determinism::check(session, || {
+        request.call(actor.clone(), gas_limit, None, deterministic)
+    })?;
+    let result = session.tx(|| request.call(actor, gas_limit, None, deterministic));
+    ensure_not_denied(session)?;
+    Ok(result)
+}
+
+/// Encrypt the input of a call to the ECDH key of the active worker.
+fn seal_input(
+    session: &mut PinkSession,
+    client: &sr25519::Pair,
+    input: &[u8],
+) -> Result<EncryptedData> {
+    let worker = worker_ecdh_pair(session)?;
+    EncryptedData::seal(client, &worker.public().0, input)
+}
+
+fn decode_output<Ret: Decode>(output: &[u8]) -> Result<Ret> {
+    MessageResult::<Ret>::decode(&mut &output[..])
         .map_err(|e| format!("Failed to decode result: {}", e))?
+        .map_err(|e| format!("Failed to execute call: {}", e).into())
+}
+
+/// The determinism transactions of the session run with, `Enforced` unless relaxed.
+fn tx_determinism(session: &mut PinkSession) -> Determinism {
+    state::with_state(session, |state| {
+        state.tx_determinism.unwrap_or(Determinism::Enforced)
+    })
+}
+
+/// Render a dispatch error, explaining the ones which are confusing on their own.
+fn describe_error(err: &DispatchError) -> String {
+    let indeterministic: DispatchError =
+        pallet_contracts::Error::<PinkRuntime>::Indeterministic.into();
+    match (err, indeterministic) {
+        (DispatchError::Module(err), DispatchError::Module(expected))
+            if err.index == expected.index && err.error == expected.error =>
+        {
+            "Indeterministic: code uploaded with `Determinism::Relaxed` can only run in queries, \
+             upload it with enforced determinism or relax the transaction determinism"
+                .into()
+        }
+        _ => format!("{err:?}"),
+    }
+}
+
+fn decode_call_result<Ret: Decode>(
+    session: &mut PinkSession,
+    result: ContractExecResult,
+) -> Result<CallResult<Ret>> {
+    let exec_result = result.result.map_err(|e| {
+        let denial = match e {
+            DispatchError::BadOrigin => call_denial(session),
+            _ => None,
+        };
+        let reason = denial.map_or_else(|| describe_error(&e), |denial| denial.to_string());
+        format!("Failed to execute call: {reason}")
+    })?;
+    let value = MessageResult::<Ret>::decode(&mut &exec_result.data[..])
+        .map_err(|e| {
+            if exec_result.did_revert() {
+                format!("Contract reverted: 0x{}", hex::encode(&exec_result.data))
+            } else {
+                format!("Failed to decode result: {}", e)
+            }
+        })?
         .map_err(|e| format!("Failed to execute call: {}", e))?;
-    Ok(ret)
+    Ok(CallResult {
+        value,
+        gas_consumed: result.gas_consumed,
+        gas_required: result.gas_required,
+        storage_deposit: result.storage_deposit,
+        debug_message: String::from_utf8_lossy(&result.debug_message).into_owned(),
+    })
 }
 
 fn bare_call<Env, Args, Ret>(
@@ -290,25 +1412,69 @@ where
     Env: Environment<Balance = Balance>,
     Args: Encode,
 {
-    let params = call_builder.params();
-    let data = params.exec_input().encode();
-    let callee = params.callee();
-    let address: [u8; 32] = callee.as_ref().try_into().expect("Invalid callee");
-    let gas_limit = if params.gas_limit() > 0 {
-        params.gas_limit()
-    } else if deterministic {
+    let request = CallRequest::new(call_builder);
+    let gas_limit = request.gas_limit_or(if deterministic {
         DEFAULT_TX_GAS_LIMIT
     } else {
         DEFAULT_QUERY_GAS_LIMIT
-    };
+    });
+    request.call(actor, gas_limit, None, deterministic)
+}
 
-    PinkRuntime::bare_call(
-        actor,
-        address.into(),
-        *params.transferred_value(),
-        gas_limit,
-        None,
-        data,
-        deterministic,
-    )
+struct CallRequest {
+    dest: AccountId,
+    value: Balance,
+    gas_limit: u64,
+    data: Vec<u8>,
+}
+
+impl CallRequest {
+    fn new<Env, Args, Ret>(
+        call_builder: CallBuilder<
+            Env,
+            Set<Call<Env>>,
+            Set<ExecutionInput<Args>>,
+            Set<ReturnType<Ret>>,
+        >,
+    ) -> Self
+    where
+        Env: Environment<Balance = Balance>,
+        Args: Encode,
+    {
+        let params = call_builder.params();
+        let address: [u8; 32] = params.callee().as_ref().try_into().expect("Invalid callee");
+        Self {
+            dest: address.into(),
+            value: *params.transferred_value(),
+            gas_limit: params.gas_limit(),
+            data: params.exec_input().encode(),
+        }
+    }
+
+    /// The gas limit set on the builder, or `default` if none was set.
+    fn gas_limit_or(&self, default: u64) -> u64 {
+        if self.gas_limit > 0 {
+            self.gas_limit
+        } else {
+            default
+        }
+    }
+
+    fn call(
+        &self,
+        actor: AccountId,
+        gas_limit: u64,
+        storage_deposit_limit: Option<Balance>,
+        deterministic: bool,
+    ) -> ContractExecResult {
+        PinkRuntime::bare_call(
+            actor,
+            self.dest.clone(),
+            self.value,
+            gas_limit,
+            storage_deposit_limit,
+            self.data.clone(),
+            deterministic,
+        )
+    }
 }
@@ -0,0 +1,54 @@
+//! Resolve contract storage keys from the ink! storage layout in the contract metadata.
+
+use ink::metadata::layout::Layout;
+use scale::Encode;
+use scale_info::form::PortableForm;
+
+use crate::{ContractMetadata, Result};
+
+/// Storage keys of the fields of the contract.
+///
+/// Fields are addressed by their dotted path from the contract struct, e.g. `"balances"` or
+/// `"config.owner"`. Only fields stored in a cell of their own (`Mapping`, `Lazy` and the
+/// contract struct itself, addressed by `""`) have a storage key; other fields are packed into
+/// the cell of their parent.
+impl ContractMetadata {
+    /// The storage key of the cell holding the field at `path`.
+    pub fn key_of(&self, path: &str) -> Result<Vec<u8>> {
+        match self.find_field(path)? {
+            Layout::Root(root) => Ok(root.root_key().key().encode()),
+            _ => Err(format!(
+                "PackedStorageField: `{path}` is stored in the cell of its parent, access the parent instead"
+            )
+            .into()),
+        }
+    }
+
+    /// The storage key of the entry `key` of the `Mapping` at `path`.
+    pub fn mapping_key<K: Encode>(&self, path: &str, key: &K) -> Result<Vec<u8>> {
+        let mut storage_key = self.key_of(path)?;
+        key.encode_to(&mut storage_key);
+        Ok(storage_key)
+    }
+
+    fn find_field(&self, path: &str) -> Result<&Layout<PortableForm>> {
+        let mut layout = self.project().layout();
+        for name in path.split('.').filter(|name| !name.is_empty()) {
+            layout = field(layout, name)
+                .ok_or_else(|| format!("UnknownStorageField: `{name}` in `{path}`"))?;
+        }
+        Ok(layout)
+    }
+}
+
+fn field<'a>(layout: &'a Layout<PortableForm>, name: &str) -> Option<&'a Layout<PortableForm>> {
+    match layout {
+        Layout::Root(root) => field(root.layout(), name),
+        Layout::Struct(fields) => fields
+            .fields()
+            .iter()
+            .find(|field| field.name() == name)
+            .map(|field| field.layout()),
+        _ => None,
+    }
+}
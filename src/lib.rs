@@ -1,12 +1,55 @@
 pub use drink;
+pub use pallet_contracts::Determinism;
 
-pub use error::{Error, Result};
-pub use ink_helper::{code_hash, Callable, DeployBundle, Deployable, SessionExt};
+pub use encryption::EncryptedData;
+pub use envelope::{
+    Certificate, CertificateBody, CertifiedSigner, ContractQuery, ContractQueryHead,
+    EnvelopeSigner, QueryEnvelope, Signature, SignatureType, WrapBytes,
+};
+pub use error::{CallError, Error, Result};
+pub use event_chain::{ContractEvent, EventBlock};
+pub use ext::{ExtFunction, Fault, FaultInjection};
+pub use import::{ContractDump, KeyFormat};
+pub use ink_helper::{
+    code_hash, CallResult, Callable, ChainExt, ClusterExt, DeployBundle, Deployable,
+    DeterminismExt, MockExt, SessionExt, StorageExt, TracingExt, WorkerExt,
+};
+pub use metadata::ContractMetadata;
+pub use permission::{PermissionDenial, Role};
 pub use runtime::PinkRuntime;
+pub use snapshot::Snapshot;
+pub use state::{ContractLog, NondeterministicCall, WorkerId};
+pub use trace::{CallKind, CallTrace, TraceEntry};
+pub use types::{ClusterId, ExecMode, NondeterminismPolicy};
+pub use worker::WorkerIdentity;
 
+pub mod accounts;
+
+/// The helper traits of sessions and contract calls, to bring them all into scope at once.
+pub mod prelude {
+    pub use crate::{
+        Callable, ChainExt, ClusterExt, DeployBundle, Deployable, DeterminismExt, MockExt,
+        SessionExt, StorageExt, TracingExt, WorkerExt,
+    };
+}
+mod cluster;
+mod determinism;
+mod encryption;
+mod envelope;
 mod error;
+mod event_chain;
+mod ext;
+mod import;
+mod layout;
+mod metadata;
+mod permission;
 mod runtime;
+mod snapshot;
+mod state;
+mod storage;
+mod trace;
 mod types;
+mod worker;
 
 mod blocking;
 mod ink_helper;
@@ -0,0 +1,109 @@
+//! The ink! metadata of a contract, used to decode its messages and to find its storage keys.
+
+use std::path::Path;
+
+use ink::metadata::{InkProject, MessageParamSpec, ReturnTypeSpec};
+use scale_info::form::PortableForm;
+
+use crate::trace::CallKind;
+use crate::Result;
+
+/// The metadata of an ink! contract, as found in its `.contract` bundle or `metadata.json`.
+///
+/// Registered with [`TracingExt::register_metadata`](crate::TracingExt::register_metadata), it
+/// decodes the calls to the contract in traces. Its storage layout gives the keys of the
+/// contract storage, see [`key_of`](Self::key_of).
+#[derive(Debug)]
+pub struct ContractMetadata {
+    project: InkProject,
+}
+
+/// A constructor or a message.
+struct Entry<'a> {
+    label: &'a str,
+    args: &'a [MessageParamSpec<PortableForm>],
+    return_type: &'a ReturnTypeSpec<PortableForm>,
+}
+
+impl ContractMetadata {
+    pub fn new(project: InkProject) -> Self {
+        Self { project }
+    }
+
+    /// Parse the metadata from a `.contract` bundle or a `metadata.json` file content.
+    pub fn from_json(json: &str) -> Result<Self> {
+        let project =
+            serde_json::from_str(json).map_err(|err| format!("InvalidMetadata: {err}"))?;
+        Ok(Self::new(project))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let json = std::fs::read_to_string(path.as_ref())
+            .map_err(|err| format!("FailedToReadMetadata({}): {err}", path.as_ref().display()))?;
+        Self::from_json(&json)
+    }
+
+    pub(crate) fn project(&self) -> &InkProject {
+        &self.project
+    }
+
+    /// Render the input of a call as `label(arg: value, ..)`.
+    pub(crate) fn decode_input(&self, kind: CallKind, input: &[u8]) -> Option<String> {
+        let entry = self.find(kind, input)?;
+        let data = &mut &input[4..];
+        let args = entry
+            .args
+            .iter()
+            .map(|arg| {
+                Some(format!(
+                    "{}: {}",
+                    arg.label(),
+                    self.decode(arg.ty().ty().id, data)?
+                ))
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(format!("{}({})", entry.label, args.join(", ")))
+    }
+
+    /// Render the output of a call given its input.
+    pub(crate) fn decode_output(
+        &self,
+        kind: CallKind,
+        input: &[u8],
+        output: &[u8],
+    ) -> Option<String> {
+        let ty = self.find(kind, input)?.return_type.opt_type()?;
+        self.decode(ty.ty().id, &mut &output[..])
+    }
+
+    fn find(&self, kind: CallKind, input: &[u8]) -> Option<Entry> {
+        let selector = input.get(..4)?;
+        let spec = self.project.spec();
+        match kind {
+            CallKind::Call => spec
+                .messages()
+                .iter()
+                .find(|message| message.selector().to_bytes() == selector)
+                .map(|message| Entry {
+                    label: message.label(),
+                    args: message.args(),
+                    return_type: message.return_type(),
+                }),
+            CallKind::Instantiate => spec
+                .constructors()
+                .iter()
+                .find(|constructor| constructor.selector().to_bytes() == selector)
+                .map(|constructor| Entry {
+                    label: constructor.label(),
+                    args: constructor.args(),
+                    return_type: constructor.return_type(),
+                }),
+        }
+    }
+
+    fn decode(&self, ty: u32, data: &mut &[u8]) -> Option<String> {
+        scale_value::scale::decode_as_type(data, ty, self.project.registry())
+            .ok()
+            .map(|value| value.to_string())
+    }
+}
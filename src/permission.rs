@@ -0,0 +1,206 @@
+//! The privileged operations of a cluster and who may perform them.
+//!
+//! The rules follow the worker and the system contract:
+//! - `balance_of` and `import_latest_system_code` may only be called by the system contract.
+//! - Pink events changing the cluster, such as `SetLogHandler` or `UpgradeRuntimeTo`, are only
+//!   honored when emitted by the system contract and ignored otherwise.
+//! - Some messages of the system contract are restricted to the cluster owner or its admins.
+//!
+//! A denied operation fails with `BadOrigin` as in production, and is recorded as a
+//! [`PermissionDenial`] naming the caller it required.
+
+use std::fmt;
+
+use frame_support::sp_runtime::DispatchError;
+use ink::MessageResult;
+use pink::PinkEvent;
+use scale::Decode;
+
+use crate::ext::ExtFunction;
+use crate::runtime::{Pink, RuntimeEvent, System};
+use crate::state;
+use crate::types::{AccountId, BlockNumber, Hash};
+
+/// The caller a privileged operation requires.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    SystemContract,
+    ClusterOwner,
+    /// The cluster owner or a contract granted admin by it.
+    Admin,
+    /// The system contract calling itself.
+    SystemContractItself,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Role::SystemContract => "the system contract",
+            Role::ClusterOwner => "the cluster owner",
+            Role::Admin => "the cluster owner or an admin",
+            Role::SystemContractItself => "the system contract itself",
+        })
+    }
+}
+
+/// A privileged operation attempted by a caller without the required role.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PermissionDenial {
+    /// E.g. "extension function `balance_of`" or "system message `set_driver`".
+    pub operation: String,
+    pub required: Role,
+    pub caller: AccountId,
+    pub block_number: BlockNumber,
+}
+
+impl fmt::Display for PermissionDenial {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "BadOrigin: {} may only be used by {}, not by {}",
+            self.operation, self.required, self.caller
+        )
+    }
+}
+
+/// Extension functions only the system contract may call.
+const SYSTEM_FUNCTIONS: [ExtFunction; 2] =
+    [ExtFunction::BalanceOf, ExtFunction::ImportLatestSystemCode];
+
+/// Messages of the system contract and the role they require.
+const SYSTEM_MESSAGES: [(&str, Role); 9] = [
+    ("grant_admin", Role::ClusterOwner),
+    ("set_driver", Role::ClusterOwner),
+    ("upgrade_system_contract", Role::ClusterOwner),
+    ("upgrade_runtime", Role::ClusterOwner),
+    ("deploy_sidevm_to", Role::Admin),
+    ("stop_sidevm_at", Role::Admin),
+    ("set_hook", Role::Admin),
+    ("set_contract_weight", Role::Admin),
+    ("do_upgrade", Role::SystemContractItself),
+];
+
+/// The selector of a message of the system contract.
+pub(crate) fn system_selector(message: &str) -> [u8; 4] {
+    let hash = sp_core::hashing::blake2_256(format!("pink_system::System::{message}").as_bytes());
+    hash[..4].try_into().expect("4 bytes out of 32")
+}
+
+/// The name and required role of a restricted system contract message.
+pub(crate) fn system_message(selector: &[u8]) -> Option<(&'static str, Role)> {
+    SYSTEM_MESSAGES
+        .into_iter()
+        .find(|(name, _)| selector.get(..4) == Some(&system_selector(name)[..]))
+}
+
+/// The name of a pink event only the system contract may emit.
+fn system_only_event(event: &PinkEvent) -> Option<&'static str> {
+    match event {
+        PinkEvent::DeploySidevmTo { .. } => Some("DeploySidevmTo"),
+        PinkEvent::ForceStopSidevm { .. } => Some("ForceStopSidevm"),
+        PinkEvent::SetLogHandler(_) => Some("SetLogHandler"),
+        PinkEvent::SetContractWeight { .. } => Some("SetContractWeight"),
+        PinkEvent::UpgradeRuntimeTo { .. } => Some("UpgradeRuntimeTo"),
+        _ => None,
+    }
+}
+
+pub(crate) fn is_system_contract(account: &AccountId) -> bool {
+    Pink::system_contract().as_ref() == Some(account)
+}
+
+/// Fail with `BadOrigin` if `function` is restricted to the system contract and `caller` is
+/// another contract.
+pub(crate) fn ensure_ext_allowed(
+    caller: &AccountId,
+    function: ExtFunction,
+) -> Result<(), DispatchError> {
+    if !SYSTEM_FUNCTIONS.contains(&function) || is_system_contract(caller) {
+        return Ok(());
+    }
+    deny(
+        format!("extension function `{function}`"),
+        Role::SystemContract,
+        caller,
+    );
+    Err(DispatchError::BadOrigin)
+}
+
+/// Record that `operation` was denied to `caller` for not being the cluster owner.
+pub(crate) fn deny_owner(caller: &AccountId, operation: &str) -> PermissionDenial {
+    deny(operation.to_string(), Role::ClusterOwner, caller)
+}
+
+/// Record a denial of a system contract message which returned `Error::BadOrigin`.
+pub(crate) fn check_system_call(caller: &AccountId, input: &[u8], output: &[u8]) {
+    let Some((message, required)) = system_message(input) else {
+        return;
+    };
+    let result = MessageResult::<Result<(), pink::system::Error>>::decode(&mut &output[..]);
+    if matches!(result, Ok(Ok(Err(pink::system::Error::BadOrigin)))) {
+        deny(format!("system message `{message}`"), required, caller);
+    }
+}
+
+/// Record the system-only pink events deposited since `first_event` by other contracts. The
+/// worker ignores them.
+pub(crate) fn check_pink_events(first_event: u32) {
+    let pink_topic: Hash = PinkEvent::event_topic().into();
+    for record in System::events().into_iter().skip(first_event as usize) {
+        if !record.topics.contains(&pink_topic) {
+            continue;
+        }
+        let RuntimeEvent::Contracts(pallet_contracts::Event::ContractEmitted { contract, data }) =
+            record.event
+        else {
+            continue;
+        };
+        if is_system_contract(&contract) {
+            continue;
+        }
+        let Ok(event) = PinkEvent::decode(&mut &data[..]) else {
+            continue;
+        };
+        if let Some(name) = system_only_event(&event) {
+            deny(
+                format!("pink event `{name}`"),
+                Role::SystemContract,
+                &contract,
+            );
+        }
+    }
+}
+
+/// Start checking the permissions of a call made by `origin`. Returns the number of denials
+/// recorded so far, to be passed to [`end`].
+pub(crate) fn begin(origin: &AccountId) -> usize {
+    state::with(|state| {
+        state.call_stack = vec![origin.clone()];
+        state.permission_denials.len()
+    })
+    .unwrap_or(0)
+}
+
+/// Finish checking the permissions of a call, remembering the last denial recorded since
+/// [`begin`] to explain the failure of the call.
+pub(crate) fn end(denials: usize) {
+    state::with(|state| {
+        state.call_stack.clear();
+        state.call_denial = state
+            .permission_denials
+            .get(denials..)
+            .and_then(|new| new.last().cloned());
+    });
+}
+
+fn deny(operation: String, required: Role, caller: &AccountId) -> PermissionDenial {
+    let denial = PermissionDenial {
+        operation,
+        required,
+        caller: caller.clone(),
+        block_number: System::block_number(),
+    };
+    log::warn!(target: "pink", "{denial}");
+    state::with(|state| state.permission_denials.push(denial.clone()));
+    denial
+}
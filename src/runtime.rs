@@ -1,10 +1,11 @@
 use crate::runtime::pallet_pink::JsRuntime;
-use crate::types::{AccountId, Balance, BlockNumber, ExecMode, Hash, Hashing, Nonce};
+use crate::types::{AccountId, Balance, BlockNumber, ClusterId, ExecMode, Hash, Hashing, Nonce};
 use drink::runtime::{AccountIdFor, Runtime, RuntimeMetadataPrefixed};
+use drink::session::Session;
 use frame_support::sp_runtime::{self, BuildStorage as _};
 use frame_support::{
     parameter_types,
-    traits::{ConstBool, ConstU32, Randomness},
+    traits::{ConstBool, ConstU32, Currency, Randomness},
     weights::{constants::WEIGHT_REF_TIME_PER_SECOND, Weight},
 };
 use pallet_contracts::{
@@ -16,8 +17,8 @@ use pallet_contracts::{CollectEvents, DebugInfo, Determinism};
 use pallet_contracts_primitives::Code;
 use scale::Encode;
 use sp_runtime::{
-    traits::{Dispatchable, IdentityLookup},
-    Perbill,
+    traits::{Dispatchable, Header as _, IdentityLookup},
+    DispatchError, Perbill,
 };
 
 pub type ContractExecResult =
@@ -30,6 +31,7 @@ pub type ContractInstantiateResult = pallet_contracts_primitives::ContractInstan
 
 mod extension;
 mod pallet_pink;
+mod tracing;
 
 type Block = sp_runtime::generic::Block<
     sp_runtime::generic::Header<BlockNumber, Hashing>,
@@ -178,17 +180,45 @@ impl Config for PinkRuntime {
     type CodeHashLockupDepositPercent = CodeHashLockupDepositPercent;
     type MaxDelegateDependencies = ConstU32<32>;
     type RuntimeHoldReason = RuntimeHoldReason;
-    type Debug = ();
+    type Debug = tracing::Tracer;
     type Environment = ();
 }
 
-/// Default initial balance for the default account.
+environmental::environmental!(skip_cluster_setup: bool);
+environmental::environmental!(cluster_setup: ClusterSetup);
+
+/// The cluster created in the genesis block of a session.
+#[derive(Debug, Clone)]
+pub(crate) struct ClusterSetup {
+    pub cluster_id: ClusterId,
+    pub owner: AccountId,
+}
+
+impl Default for ClusterSetup {
+    fn default() -> Self {
+        Self {
+            cluster_id: ClusterId::zero(),
+            owner: PinkRuntime::default_actor(),
+        }
+    }
+}
+
+/// Default initial balance for the default account and the dev accounts.
 pub const INITIAL_BALANCE: u128 = 1_000_000_000_000_000_000_000;
 
 impl Runtime for PinkRuntime {
     fn initialize_storage(storage: &mut sp_runtime::Storage) -> Result<(), String> {
+        let dev_accounts = crate::accounts::DEV_ACCOUNTS.iter().map(|name| {
+            (
+                crate::accounts::TestAccount::new(name).account_id(),
+                INITIAL_BALANCE,
+            )
+        });
         pallet_balances::GenesisConfig::<Self> {
-            balances: vec![(Self::default_actor(), INITIAL_BALANCE)],
+            balances: [(Self::default_actor(), INITIAL_BALANCE)]
+                .into_iter()
+                .chain(dev_accounts)
+                .collect(),
         }
         .assimilate_storage(storage)
     }
@@ -197,13 +227,20 @@ impl Runtime for PinkRuntime {
         AccountId::new([1u8; 32])
     }
 
-    fn initialize_block(height: BlockNumber, _parent_hash: Hash) -> Result<(), String> {
-        if height != 1 {
+    fn initialize_block(height: BlockNumber, parent_hash: Hash) -> Result<(), String> {
+        System::reset_events();
+        System::initialize(&height, &parent_hash, &Default::default());
+        // The cluster is set up in the genesis block, which `Sandbox::new` initializes.
+        if height != 1 || skip_cluster_setup::with(|skip| *skip).unwrap_or(false) {
             return Ok(());
         }
         Self::setup_cluster()
     }
 
+    fn finalize_block(_height: BlockNumber) -> Result<Hash, String> {
+        Ok(System::finalize().hash())
+    }
+
     fn get_metadata() -> RuntimeMetadataPrefixed {
         Self::metadata()
     }
@@ -218,15 +255,20 @@ impl Runtime for PinkRuntime {
 impl PinkRuntime {
     fn setup_cluster() -> Result<(), String> {
         type PalletPink = Pink;
-        PalletPink::set_cluster_id(Hash::zero());
-        PalletPink::set_gas_price(0);
-        PalletPink::set_deposit_per_item(0);
-        PalletPink::set_deposit_per_byte(0);
+        let ClusterSetup { cluster_id, owner } =
+            cluster_setup::with(|setup| setup.clone()).unwrap_or_default();
+        PalletPink::set_cluster_id(cluster_id);
+        PalletPink::set_key(Self::cluster_key(&cluster_id));
         PalletPink::set_treasury_account(&[0u8; 32].into());
 
         let system_code = include_bytes!("../artifacts/system.wasm").to_vec();
 
-        let owner = PinkRuntime::default_actor();
+        if Balances::total_balance(&owner) == 0 {
+            let _ = Balances::deposit_creating(&owner, INITIAL_BALANCE);
+        }
+        PalletPink::set_cluster_owner(&owner);
+        PalletPink::set_cluster_prices(&owner, 0, 0, 0)
+            .map_err(|err| format!("FailedToSetClusterPrices: {err:?}"))?;
         let system_code_hash = Self::upload_code(owner.clone(), system_code, true)
             .map_err(|err| format!("FailedToUploadSystemCode: {err:?}"))?;
 
@@ -287,6 +329,65 @@ impl PinkRuntime {
         Ok(())
     }
 
+    /// Run `f` with the cluster setup in the genesis block disabled, e.g. when the state is
+    /// going to be restored from a snapshot.
+    pub(crate) fn without_cluster_setup<T>(f: impl FnOnce() -> T) -> T {
+        skip_cluster_setup::using(&mut true, f)
+    }
+
+    /// Run `f` with the cluster setup in the genesis block creating the given cluster.
+    pub(crate) fn with_cluster_setup<T>(mut setup: ClusterSetup, f: impl FnOnce() -> T) -> T {
+        cluster_setup::using(&mut setup, f)
+    }
+
+    /// Start a session whose cluster is owned by `owner` instead of the default actor. The owner
+    /// is endowed if it has no balance.
+    pub fn session_with_cluster_owner(owner: AccountId) -> crate::Result<Session<Self>> {
+        let setup = ClusterSetup {
+            cluster_id: ClusterId::zero(),
+            owner,
+        };
+        Self::with_cluster_setup(setup, Session::<Self>::new)
+            .map_err(|err| format!("FailedToCreateSession: {err:?}").into())
+    }
+
+    /// The system contract of the active cluster. Must be called with the session externalities,
+    /// e.g. in [`SessionExt::query`](crate::SessionExt::query).
+    pub fn system_contract() -> Option<AccountId> {
+        Pink::system_contract()
+    }
+
+    /// Set the gas price and storage deposits of the active cluster on behalf of `origin`.
+    /// Anyone but the cluster owner is denied with `BadOrigin`.
+    pub fn set_cluster_prices(
+        origin: &AccountId,
+        gas_price: Balance,
+        deposit_per_item: Balance,
+        deposit_per_byte: Balance,
+    ) -> crate::Result<()> {
+        Pink::set_cluster_prices(origin, gas_price, deposit_per_item, deposit_per_byte).map_err(
+            |err| match err {
+                DispatchError::BadOrigin => {
+                    crate::permission::deny_owner(origin, "setting the cluster prices")
+                        .to_string()
+                        .into()
+                }
+                err => format!("FailedToSetClusterPrices: {err:?}").into(),
+            },
+        )
+    }
+
+    /// A key for the cluster derived from its id, so that each cluster has its own key.
+    fn cluster_key(cluster_id: &ClusterId) -> [u8; 64] {
+        use sp_core::Pair as _;
+
+        let seed = sp_core::hashing::blake2_256(&(b"pink-drink:cluster_key:", cluster_id).encode());
+        sp_core::sr25519::Pair::from_seed(&seed)
+            .to_raw_vec()
+            .try_into()
+            .expect("sr25519 secret keys are 64 bytes")
+    }
+
     pub(crate) fn execute_in_mode<T>(mode: ExecMode, f: impl FnOnce() -> T) -> T {
         extension::exec_in_mode(mode, f)
     }
@@ -349,17 +450,26 @@ impl PinkRuntime {
         data: Vec<u8>,
         salt: Vec<u8>,
     ) -> ContractInstantiateResult {
-        Contracts::bare_instantiate(
-            origin,
+        crate::trace::begin(&origin, value);
+        let first_event = System::event_count();
+        let denials = crate::permission::begin(&origin);
+        let result = Contracts::bare_instantiate(
+            origin.clone(),
             value,
             Weight::from_parts(gas_limit, u64::MAX),
             storage_deposit_limit,
             Code::Existing(code_hash),
             data,
             salt,
-            DebugInfo::Skip,
+            DebugInfo::UnsafeDebug,
             CollectEvents::Skip,
-        )
+        );
+        crate::trace::end(result.gas_consumed);
+        let instantiated = result.result.as_ref().ok();
+        after_transaction(&origin, instantiated.map(|v| &v.account_id), first_event);
+        crate::permission::end(denials);
+        record_debug_message(&result.debug_message);
+        result
     }
 
     pub fn call(
@@ -395,9 +505,12 @@ impl PinkRuntime {
         data: Vec<u8>,
         deterministic: bool,
     ) -> ContractExecResult {
+        crate::trace::begin(&origin, value);
+        let first_event = System::event_count();
+        let denials = crate::permission::begin(&origin);
         let result = Contracts::bare_call(
-            origin,
-            dest,
+            origin.clone(),
+            dest.clone(),
             value,
             Weight::from_parts(gas_limit, u64::MAX),
             storage_deposit_limit,
@@ -410,9 +523,32 @@ impl PinkRuntime {
                 Determinism::Relaxed
             },
         );
-        if !result.debug_message.is_empty() {
-            log::debug!("Debug message: {:?}", String::from_utf8_lossy(&result.debug_message));
-        }
+        crate::trace::end(result.gas_consumed);
+        after_transaction(&origin, Some(&dest), first_event);
+        crate::permission::end(denials);
+        record_debug_message(&result.debug_message);
         result
     }
 }
+
+/// Process the events deposited by a transaction since `first_event`.
+fn after_transaction(origin: &AccountId, entry_contract: Option<&AccountId>, first_event: u32) {
+    if extension::current_mode() != ExecMode::Transaction {
+        return;
+    }
+    crate::permission::check_pink_events(first_event);
+    // Events of failed or reverted calls are rolled back, so only successful calls get here.
+    extension::queue_cache_ops(first_event);
+    if let Some(entry_contract) = entry_contract {
+        crate::event_chain::emit(origin, entry_contract, first_event);
+    }
+}
+
+fn record_debug_message(debug_message: &[u8]) {
+    if debug_message.is_empty() {
+        return;
+    }
+    let message = String::from_utf8_lossy(debug_message).into_owned();
+    log::debug!("Debug message: {:?}", message);
+    crate::state::with(|state| state.debug_messages.push(message));
+}
@@ -17,11 +17,14 @@ use pink::{
     CacheOp, EcdhPublicKey, EcdsaPublicKey, EcdsaSignature, Hash, PinkEvent,
 };
 use pink_chain_extension::{DefaultPinkExtension, PinkRuntimeEnv};
-use scale::Encode;
+use scale::{Decode, Encode};
 
 use super::{pallet_pink, PinkRuntime};
+use crate::determinism::TxInputs;
+use crate::ext::{ExtFunction, Fault};
 use crate::runtime::Pink as PalletPink;
-use crate::types::{AccountId, ExecMode};
+use crate::state::{ContractLog, NondeterministicCall};
+use crate::types::{AccountId, ExecMode, NondeterminismPolicy};
 use pink::ConvertTo as _;
 
 type Error = pallet_pink::Error<PinkRuntime>;
@@ -35,12 +38,40 @@ fn deposit_pink_event(contract: AccountId, event: PinkEvent) {
     super::System::deposit_event_indexed(&topics[..], event);
 }
 
+/// Queue the cache operations emitted since `first_event`, to be applied to the workers once
+/// the transaction is committed.
+pub(crate) fn queue_cache_ops(first_event: u32) {
+    let pink_topic: crate::types::Hash = PinkEvent::event_topic().into();
+    let ops: Vec<_> = super::System::events()
+        .into_iter()
+        .skip(first_event as usize)
+        .filter(|record| record.topics.contains(&pink_topic))
+        .filter_map(|record| match record.event {
+            super::RuntimeEvent::Contracts(pallet_contracts::Event::ContractEmitted {
+                contract,
+                data,
+            }) => match PinkEvent::decode(&mut &data[..]) {
+                Ok(PinkEvent::CacheOp(op)) => Some((contract, op)),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+    if !ops.is_empty() {
+        crate::state::with(|state| state.pending_cache_ops.extend(ops));
+    }
+}
+
 environmental::environmental!(exec_mode: ExecMode);
 
 pub(crate) fn exec_in_mode<T>(mut mode: ExecMode, f: impl FnOnce() -> T) -> T {
     exec_mode::using(&mut mode, f)
 }
 
+pub(crate) fn current_mode() -> ExecMode {
+    exec_mode::with(|value| *value).unwrap_or(ExecMode::Query)
+}
+
 /// Contract extension for `pink contracts`
 #[derive(Default)]
 pub struct PinkExtension;
@@ -56,14 +87,43 @@ impl ChainExtension<PinkRuntime> for PinkExtension {
             return Err(Error::UnknownChainExtensionId.into());
         }
 
+        crate::trace::ext_call(env.func_id());
         let address = env.ext().address().clone();
+        // Checked before faults and mocks, which must not let other contracts through.
+        if let Some(function) = ExtFunction::from_func_id(env.func_id()) {
+            crate::permission::ensure_ext_allowed(&address, function)?;
+        }
+        let fault = crate::state::with(|state| state.faults.hit(env.func_id(), &address));
+        match fault.flatten() {
+            Some(Fault::Error(err)) => return Err(err),
+            Some(Fault::Timeout) => {
+                let gas_left = env.ext().gas_meter().gas_left();
+                env.charge_weight(gas_left)?;
+                return Err(pallet_contracts::Error::<PinkRuntime>::OutOfGas.into());
+            }
+            Some(Fault::MalformedOutput(output)) => {
+                env.write(&output, false, None)
+                    .or(Err(Error::ContractIoBufferOverflow))?;
+                return Ok(RetVal::Converging(0));
+            }
+            None => {}
+        }
+        let mock = crate::state::with(|state| state.ext_mock(env.func_id(), &address));
+        if let Some(mock) = mock.flatten() {
+            let input = env.read(env.in_len())?;
+            let output = mock(&input)?;
+            env.write(&output, false, None)
+                .or(Err(Error::ContractIoBufferOverflow))?;
+            return Ok(RetVal::Converging(0));
+        }
         let call_in_query = CallInQuery { address };
-        let mode = exec_mode::with(|value| *value).unwrap_or(ExecMode::Query);
+        let mode = current_mode();
         let (ret, output) = if mode.is_query() {
             dispatch_ext_call!(env.func_id(), call_in_query, env)
         } else {
             let call = CallInCommand {
                 as_in_query: call_in_query,
+                func_id: env.func_id(),
             };
             dispatch_ext_call!(env.func_id(), call, env)
         }
@@ -94,16 +154,6 @@ impl PinkRuntimeEnv for CallInQuery {
     }
 }
 
-impl CallInQuery {
-    fn ensure_system(&self) -> Result<(), DispatchError> {
-        let contract: AccountId32 = self.address.convert_to();
-        if Some(contract) != PalletPink::system_contract() {
-            return Err(DispatchError::BadOrigin);
-        }
-        Ok(())
-    }
-}
-
 impl PinkExtBackend for CallInQuery {
     type Error = DispatchError;
     fn http_request(&self, request: HttpRequest) -> Result<HttpResponse, Self::Error> {
@@ -137,8 +187,19 @@ impl PinkExtBackend for CallInQuery {
         DefaultPinkExtension::new(self).verify(sigtype, pubkey, message, signature)
     }
 
+    /// Derived from the key of the cluster, so each cluster gives its contracts other keys.
     fn derive_sr25519_key(&self, salt: Cow<[u8]>) -> Result<Vec<u8>, Self::Error> {
-        DefaultPinkExtension::new(self).derive_sr25519_key(salt)
+        use sp_core::crypto::{DeriveJunction, Pair as _};
+
+        let key = PalletPink::key().ok_or(pallet_pink::Error::<PinkRuntime>::KeySeedMissing)?;
+        let cluster_key = sp_core::sr25519::Pair::from_seed_slice(&key)
+            .map_err(|_| pallet_pink::Error::<PinkRuntime>::DeriveKeyFailed)?;
+        let path: [&[u8]; 3] = [self.address.as_ref(), &salt, b"keygen"];
+        let path = path.map(DeriveJunction::hard);
+        let (derived, _) = cluster_key
+            .derive(path.into_iter(), None)
+            .map_err(|_| pallet_pink::Error::<PinkRuntime>::DeriveKeyFailed)?;
+        Ok(derived.to_raw_vec())
     }
 
     fn get_public_key(&self, sigtype: SigType, key: Cow<[u8]>) -> Result<Vec<u8>, Self::Error> {
@@ -150,27 +211,83 @@ impl PinkExtBackend for CallInQuery {
         key: Cow<[u8]>,
         value: Cow<[u8]>,
     ) -> Result<Result<(), StorageQuotaExceeded>, Self::Error> {
+        let stored = crate::state::with(|state| {
+            state
+                .worker
+                .offchain
+                .cache_set(&self.address, key.to_vec(), value.to_vec())
+        });
+        if stored.is_some() {
+            return Ok(Ok(()));
+        }
         DefaultPinkExtension::new(self).cache_set(key, value)
     }
 
     fn cache_set_expiration(&self, key: Cow<[u8]>, expire: u64) -> Result<(), Self::Error> {
+        let stored = crate::state::with(|state| {
+            state
+                .worker
+                .offchain
+                .cache_set_expiration(&self.address, &key, expire)
+        });
+        if stored.is_some() {
+            return Ok(());
+        }
         DefaultPinkExtension::new(self).cache_set_expiration(key, expire)
     }
 
     fn cache_get(&self, key: Cow<'_, [u8]>) -> Result<Option<Vec<u8>>, Self::Error> {
-        DefaultPinkExtension::new(self).cache_get(key)
+        match crate::state::with(|state| state.worker.offchain.cache_get(&self.address, &key)) {
+            Some(value) => Ok(value),
+            None => DefaultPinkExtension::new(self).cache_get(key),
+        }
     }
 
     fn cache_remove(&self, key: Cow<'_, [u8]>) -> Result<Option<Vec<u8>>, Self::Error> {
-        DefaultPinkExtension::new(self).cache_remove(key)
+        match crate::state::with(|state| state.worker.offchain.cache_remove(&self.address, &key)) {
+            Some(value) => Ok(value),
+            None => DefaultPinkExtension::new(self).cache_remove(key),
+        }
     }
 
     fn log(&self, level: u8, message: Cow<str>) -> Result<(), Self::Error> {
+        let record = ContractLog {
+            contract: self.address.clone(),
+            level: match level {
+                1 => log::Level::Error,
+                2 => log::Level::Warn,
+                3 => log::Level::Info,
+                4 => log::Level::Debug,
+                _ => log::Level::Trace,
+            },
+            message: message.to_string(),
+            block_number: super::System::block_number(),
+            mode: current_mode(),
+        };
+        let quiet = crate::state::with(|state| {
+            state.logs.push(record);
+            state.quiet_logs
+        })
+        .unwrap_or(false);
+        if quiet {
+            return Ok(());
+        }
         DefaultPinkExtension::new(self).log(level, message)
     }
 
     fn getrandom(&self, length: u8) -> Result<Vec<u8>, Self::Error> {
-        DefaultPinkExtension::new(self).getrandom(length)
+        let seeded = crate::state::with(|state| {
+            state
+                .worker
+                .offchain
+                .rng
+                .as_mut()
+                .map(|rng| rng.fill(length as usize))
+        });
+        match seeded.flatten() {
+            Some(bytes) => Ok(bytes),
+            None => DefaultPinkExtension::new(self).getrandom(length),
+        }
     }
 
     fn is_in_transaction(&self) -> Result<bool, Self::Error> {
@@ -204,7 +321,6 @@ impl PinkExtBackend for CallInQuery {
         &self,
         account: ext::AccountId,
     ) -> Result<(pink::Balance, pink::Balance), Self::Error> {
-        self.ensure_system()?;
         let account: AccountId32 = account.convert_to();
         let total = crate::runtime::Balances::total_balance(&account);
         let free = crate::runtime::Balances::free_balance(&account);
@@ -212,11 +328,17 @@ impl PinkExtBackend for CallInQuery {
     }
 
     fn untrusted_millis_since_unix_epoch(&self) -> Result<u64, Self::Error> {
-        DefaultPinkExtension::new(self).untrusted_millis_since_unix_epoch()
+        match crate::state::with(|state| state.worker.offchain.clock).flatten() {
+            Some(millis) => Ok(millis),
+            None => DefaultPinkExtension::new(self).untrusted_millis_since_unix_epoch(),
+        }
     }
 
     fn worker_pubkey(&self) -> Result<EcdhPublicKey, Self::Error> {
-        Ok(Default::default())
+        let identity = crate::state::with(|state| state.worker.identity.clone()).flatten();
+        Ok(identity
+            .map(|identity| identity.ecdh_public_key())
+            .unwrap_or_default())
     }
 
     fn code_exists(&self, code_hash: Hash, sidevm: bool) -> Result<bool, Self::Error> {
@@ -231,7 +353,6 @@ impl PinkExtBackend for CallInQuery {
         &self,
         _payer: ext::AccountId,
     ) -> Result<Option<Hash>, Self::Error> {
-        self.ensure_system()?;
         return Ok(None);
     }
 
@@ -281,6 +402,10 @@ impl PinkExtBackend for CallInQuery {
     }
 
     fn worker_sgx_quote(&self) -> Result<Option<SgxQuote>, Self::Error> {
+        let identity = crate::state::with(|state| state.worker.identity.clone()).flatten();
+        if let Some(quote) = identity.as_ref().and_then(|identity| identity.sgx_quote()) {
+            return Ok(Some(quote.clone()));
+        }
         pink_chain_extension::mock_ext::MockExtension
             .worker_sgx_quote()
             .map_err(|_| "No SGX quote".into())
@@ -289,6 +414,54 @@ impl PinkExtBackend for CallInQuery {
 
 struct CallInCommand {
     as_in_query: CallInQuery,
+    func_id: u32,
+}
+
+impl CallInCommand {
+    /// Apply the nondeterminism policy of the session to a call answered with a stub value.
+    fn nondeterministic(&self, function: &'static str) -> Result<(), DispatchError> {
+        let call = NondeterministicCall {
+            contract: self.as_in_query.address.clone(),
+            func_id: self.func_id,
+            function,
+            block_number: super::System::block_number(),
+        };
+        let policy = crate::state::with(|state| {
+            let policy = state.nondeterminism_policy;
+            if policy != NondeterminismPolicy::Allow {
+                state.nondeterministic_calls.push(call.clone());
+            }
+            if policy == NondeterminismPolicy::Deny {
+                state.denied_call.get_or_insert(call.clone());
+            }
+            policy
+        });
+        match policy.unwrap_or_default() {
+            NondeterminismPolicy::Allow => Ok(()),
+            NondeterminismPolicy::Warn => {
+                log::warn!(target: "pink", "Nondeterministic extension call: {call}");
+                Ok(())
+            }
+            NondeterminismPolicy::Deny => {
+                error!(target: "pink", "Nondeterministic extension call denied: {call}");
+                Err(DispatchError::Other("NondeterministicExtCall"))
+            }
+        }
+    }
+
+    /// Inputs replacing the stub values below while checking the determinism of a transaction.
+    fn perturbed<T>(f: impl FnOnce(&mut TxInputs) -> T) -> Option<T> {
+        crate::state::with(|state| state.tx_inputs.as_mut().map(f)).flatten()
+    }
+
+    fn perturbed_response(inputs: &mut TxInputs) -> HttpResponse {
+        HttpResponse {
+            status_code: inputs.http_status,
+            reason_phrase: "OK".into(),
+            headers: vec![],
+            body: inputs.rng.fill(32),
+        }
+    }
 }
 
 /// This implementation is used when calling the extension in a command.
@@ -298,6 +471,10 @@ impl PinkExtBackend for CallInCommand {
     type Error = DispatchError;
 
     fn http_request(&self, _request: HttpRequest) -> Result<HttpResponse, Self::Error> {
+        self.nondeterministic("http_request")?;
+        if let Some(response) = Self::perturbed(Self::perturbed_response) {
+            return Ok(response);
+        }
         Ok(HttpResponse {
             status_code: 523,
             reason_phrase: "API Unavailable".into(),
@@ -307,9 +484,19 @@ impl PinkExtBackend for CallInCommand {
     }
     fn batch_http_request(
         &self,
-        _requests: Vec<ext::HttpRequest>,
+        requests: Vec<ext::HttpRequest>,
         _timeout_ms: u64,
     ) -> Result<ext::BatchHttpResult, Self::Error> {
+        self.nondeterministic("batch_http_request")?;
+        let perturbed = Self::perturbed(|inputs| {
+            requests
+                .iter()
+                .map(|_| Ok(Self::perturbed_response(inputs)))
+                .collect()
+        });
+        if let Some(responses) = perturbed {
+            return Ok(Ok(responses));
+        }
         Ok(Err(ext::HttpRequestError::NotAllowed))
     }
     fn sign(
@@ -319,7 +506,8 @@ impl PinkExtBackend for CallInCommand {
         message: Cow<[u8]>,
     ) -> Result<Vec<u8>, Self::Error> {
         if matches!(sigtype, SigType::Sr25519) {
-            return Ok(vec![]);
+            self.nondeterministic("sign")?;
+            return Ok(Self::perturbed(|inputs| inputs.rng.fill(64)).unwrap_or_default());
         }
         self.as_in_query.sign(sigtype, key, message)
     }
@@ -369,6 +557,7 @@ impl PinkExtBackend for CallInCommand {
     }
 
     fn cache_get(&self, _key: Cow<[u8]>) -> Result<Option<Vec<u8>>, Self::Error> {
+        self.nondeterministic("cache_get")?;
         Ok(None)
     }
 
@@ -386,8 +575,9 @@ impl PinkExtBackend for CallInCommand {
         self.as_in_query.log(level, message)
     }
 
-    fn getrandom(&self, _length: u8) -> Result<Vec<u8>, Self::Error> {
-        Ok(vec![])
+    fn getrandom(&self, length: u8) -> Result<Vec<u8>, Self::Error> {
+        self.nondeterministic("getrandom")?;
+        Ok(Self::perturbed(|inputs| inputs.rng.fill(length as usize)).unwrap_or_default())
     }
 
     fn is_in_transaction(&self) -> Result<bool, Self::Error> {
@@ -424,7 +614,8 @@ impl PinkExtBackend for CallInCommand {
     }
 
     fn untrusted_millis_since_unix_epoch(&self) -> Result<u64, Self::Error> {
-        Ok(0)
+        self.nondeterministic("untrusted_millis_since_unix_epoch")?;
+        Ok(Self::perturbed(|inputs| inputs.clock).unwrap_or(0))
     }
 
     fn worker_pubkey(&self) -> Result<EcdhPublicKey, Self::Error> {
@@ -455,12 +646,14 @@ impl PinkExtBackend for CallInCommand {
         _codes: Vec<ext::JsCode>,
         _args: Vec<String>,
     ) -> Result<ext::JsValue, Self::Error> {
+        self.nondeterministic("js_eval")?;
         return Ok(ext::JsValue::Exception(
             "js_eval is not supported".to_string(),
         ));
     }
 
     fn worker_sgx_quote(&self) -> Result<Option<SgxQuote>, Self::Error> {
+        self.nondeterministic("worker_sgx_quote")?;
         Ok(None)
     }
 }
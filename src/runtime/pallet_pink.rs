@@ -81,6 +81,11 @@ pub mod pallet {
     pub(crate) type SidevmCodes<T: Config> =
         StorageMap<_, Twox64Concat, T::Hash, WasmCode<T::AccountId>>;
 
+    /// The account which instantiated the system contract and administers the cluster
+    #[pallet::storage]
+    #[pallet::getter(fn cluster_owner)]
+    pub(crate) type ClusterOwner<T: Config> = StorageValue<_, T::AccountId, OptionQuery>;
+
     /// The system contract address
     #[pallet::storage]
     #[pallet::getter(fn system_contract)]
@@ -101,6 +106,10 @@ pub mod pallet {
     #[pallet::getter(fn js_runtime)]
     pub type JsRuntime<T: Config> = StorageValue<_, Vec<u8>, ValueQuery>;
 
+    /// Address forced onto the next instantiated contract, used when importing contract state
+    #[pallet::storage]
+    pub(crate) type NextContractAddress<T: Config> = StorageValue<_, T::AccountId>;
+
     #[pallet::pallet]
     #[pallet::without_storage_info]
     pub struct Pallet<T>(_);
@@ -115,7 +124,24 @@ pub mod pallet {
             _input_data: &[u8],
             salt: &[u8],
         ) -> T::AccountId {
-            let cluster_id = <ClusterId<T>>::get();
+            if let Some(address) = <NextContractAddress<T>>::take() {
+                return address;
+            }
+            Self::contract_address_in(&<ClusterId<T>>::get(), deploying_address, code_hash, salt)
+        }
+    }
+
+    impl<T: Config> Pallet<T>
+    where
+        T::AccountId: UncheckedFrom<T::Hash> + AsRef<[u8]>,
+    {
+        /// The address a contract would get when instantiated in the given cluster.
+        pub fn contract_address_in(
+            cluster_id: &Hash,
+            deploying_address: &T::AccountId,
+            code_hash: &CodeHash<T>,
+            salt: &[u8],
+        ) -> T::AccountId {
             let buf: Vec<_> = deploying_address
                 .as_ref()
                 .iter()
@@ -155,6 +181,14 @@ pub mod pallet {
             <SidevmCodes<T>>::contains_key(code_hash)
         }
 
+        pub fn set_next_contract_address(address: Option<T::AccountId>) {
+            <NextContractAddress<T>>::set(address);
+        }
+
+        pub fn set_cluster_owner(owner: &T::AccountId) {
+            <ClusterOwner<T>>::put(owner);
+        }
+
         pub fn set_system_contract(address: &T::AccountId) {
             <SystemContract<T>>::put(address);
         }
@@ -181,16 +215,22 @@ pub mod pallet {
             <T as Config>::Currency::transfer(&treasury, user, amount, AllowDeath)
         }
 
-        pub fn set_gas_price(price: BalanceOf<T>) {
-            <GasPrice<T>>::put(price);
-        }
-
-        pub fn set_deposit_per_item(value: BalanceOf<T>) {
-            <DepositPerItem<T>>::put(value);
-        }
-
-        pub fn set_deposit_per_byte(value: BalanceOf<T>) {
-            <DepositPerByte<T>>::put(value);
+        /// Set the gas price and storage deposits on behalf of `origin`, which must be the
+        /// cluster owner.
+        pub fn set_cluster_prices(
+            origin: &T::AccountId,
+            gas_price: BalanceOf<T>,
+            deposit_per_item: BalanceOf<T>,
+            deposit_per_byte: BalanceOf<T>,
+        ) -> DispatchResult {
+            ensure!(
+                Self::cluster_owner().as_ref() == Some(origin),
+                DispatchError::BadOrigin
+            );
+            <GasPrice<T>>::put(gas_price);
+            <DepositPerItem<T>>::put(deposit_per_item);
+            <DepositPerByte<T>>::put(deposit_per_byte);
+            Ok(())
         }
 
         pub fn set_treasury_account(account: &T::AccountId) {
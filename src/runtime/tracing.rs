@@ -0,0 +1,69 @@
+use pallet_contracts::debug::{CallSpan, ExecReturnValue, ExportedFunction, Tracing};
+
+use super::PinkRuntime;
+use crate::permission;
+use crate::trace::{self, CallKind};
+use crate::types::AccountId;
+
+/// Feeds the contract calls made by pallet-contracts into the session trace.
+pub struct Tracer;
+
+impl Tracing<PinkRuntime> for Tracer {
+    type CallSpan = Span;
+
+    fn new_call_span(
+        contract_address: &AccountId,
+        entry_point: ExportedFunction,
+        input_data: &[u8],
+    ) -> Span {
+        let kind = match entry_point {
+            ExportedFunction::Constructor => CallKind::Instantiate,
+            ExportedFunction::Call => CallKind::Call,
+        };
+        trace::enter(kind, contract_address, input_data);
+        let caller = crate::state::with(|state| {
+            let caller = state.call_stack.last().cloned();
+            state.call_stack.push(contract_address.clone());
+            caller
+        });
+        let system_call = kind == CallKind::Call
+            && permission::is_system_contract(contract_address)
+            && permission::system_message(input_data).is_some();
+        let system_call = match caller.flatten() {
+            Some(caller) if system_call => Some((caller, input_data.to_vec())),
+            _ => None,
+        };
+        Span {
+            system_call,
+            returned: false,
+        }
+    }
+}
+
+pub struct Span {
+    /// The caller and input of a call to a restricted system contract message.
+    system_call: Option<(AccountId, Vec<u8>)>,
+    /// Whether `after_call` was invoked. pallet-contracts drops the span of a trapped call
+    /// without invoking it, and the caller may go on, e.g. after `try_invoke`.
+    returned: bool,
+}
+
+impl CallSpan for Span {
+    fn after_call(mut self, output: &ExecReturnValue) {
+        self.returned = true;
+        trace::exit(Some(&output.data), output.did_revert());
+        if let Some((caller, input)) = self.system_call.take() {
+            permission::check_system_call(&caller, &input, &output.data);
+        }
+    }
+}
+
+impl Drop for Span {
+    /// Close the frame whether the call returned or trapped.
+    fn drop(&mut self) {
+        if !self.returned {
+            trace::exit(None, false);
+        }
+        crate::state::with(|state| state.call_stack.pop());
+    }
+}
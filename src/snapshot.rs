@@ -0,0 +1,188 @@
+//! Snapshots of the full cluster state of a session.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use drink::session::Session;
+use frame_support::storage::unhashed;
+use scale::{Decode, Encode};
+
+use crate::state::{self, OffchainState, WorkerId, WorkerState};
+use crate::storage::{self, KeyValues};
+use crate::types::ClusterId;
+use crate::{ChainExt, PinkRuntime, Result, WorkerIdentity};
+
+/// Leading bytes of a snapshot file.
+const SNAPSHOT_MAGIC: [u8; 8] = *b"PINKSNAP";
+/// Bumped whenever the encoding of `Snapshot` changes.
+const SNAPSHOT_VERSION: u32 = 3;
+
+/// A copy of the sandbox storage, including the storage of all contracts, together with the
+/// workers of a session: their identities and off-chain state (cache, clock and randomness).
+///
+/// The state of the idle clusters of a session is captured along with the sandbox, and they are
+/// idle again in the restored session. So are the idle workers. Extension mocks can not be
+/// captured, a restored session keeps the mocks of the workers it already has.
+///
+/// The storage covers everything on chain: contract code and storage, balances and the
+/// `pallet_pink` items such as `SidevmCodes` and `JsRuntime`.
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct Snapshot {
+    chain: ChainState,
+    active_cluster: ClusterId,
+    idle_clusters: Vec<(ClusterId, ChainState)>,
+    active_worker: WorkerId,
+    /// All workers, the active one included.
+    workers: Vec<(WorkerId, WorkerSnapshot)>,
+    worker_count: WorkerId,
+}
+
+#[derive(Debug, Clone, Encode, Decode)]
+struct WorkerSnapshot {
+    offchain: OffchainState,
+    identity: Option<WorkerIdentity>,
+}
+
+/// The on-chain part of a snapshot.
+#[derive(Debug, Clone, Encode, Decode)]
+pub(crate) struct ChainState {
+    pub storage: KeyValues,
+    /// Contract storage keyed by child trie id.
+    pub contracts: Vec<(Vec<u8>, KeyValues)>,
+}
+
+impl Snapshot {
+    /// Start a new session from this snapshot without running the cluster setup.
+    pub fn new_session(&self) -> Result<Session<PinkRuntime>> {
+        let mut session = PinkRuntime::without_cluster_setup(Session::<PinkRuntime>::new)
+            .map_err(|err| format!("FailedToCreateSession: {err:?}"))?;
+        session.restore(self);
+        Ok(session)
+    }
+
+    /// Encode the snapshot into the file format used by [`Snapshot::save`].
+    ///
+    /// The format is the SCALE encoding of `(magic, version, snapshot)`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        (SNAPSHOT_MAGIC, SNAPSHOT_VERSION, self).encode()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let input = &mut &bytes[..];
+        let magic = <[u8; 8]>::decode(input).map_err(|_| "InvalidSnapshot: too short")?;
+        if magic != SNAPSHOT_MAGIC {
+            return Err("InvalidSnapshot: bad magic".into());
+        }
+        let version = u32::decode(input).map_err(|_| "InvalidSnapshot: too short")?;
+        if version != SNAPSHOT_VERSION {
+            return Err(format!("UnsupportedSnapshotVersion: {version}").into());
+        }
+        let snapshot = Self::decode(input).map_err(|err| format!("InvalidSnapshot: {err}"))?;
+        if !input.is_empty() {
+            return Err("InvalidSnapshot: trailing bytes".into());
+        }
+        Ok(snapshot)
+    }
+
+    /// Write the snapshot to a file.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        std::fs::write(path.as_ref(), self.to_bytes()).map_err(|err| {
+            format!("FailedToWriteSnapshot({}): {err}", path.as_ref().display()).into()
+        })
+    }
+
+    /// Read a snapshot written by [`Snapshot::save`].
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let bytes = std::fs::read(path.as_ref())
+            .map_err(|err| format!("FailedToReadSnapshot({}): {err}", path.as_ref().display()))?;
+        Self::from_bytes(&bytes)
+    }
+}
+
+pub(crate) fn take(session: &mut Session<PinkRuntime>) -> Snapshot {
+    let chain = take_chain(session);
+    state::with_state(session, |state| {
+        let workers = std::iter::once((state.active_worker, &state.worker))
+            .chain(state.idle_workers.iter().map(|(id, worker)| (*id, worker)))
+            .map(|(id, worker)| {
+                let worker = WorkerSnapshot {
+                    offchain: worker.offchain.clone(),
+                    identity: worker.identity.clone(),
+                };
+                (id, worker)
+            })
+            .collect();
+        Snapshot {
+            chain,
+            active_cluster: state.active_cluster,
+            idle_clusters: state.idle_clusters.clone().into_iter().collect(),
+            active_worker: state.active_worker,
+            workers,
+            worker_count: state.worker_count,
+        }
+    })
+}
+
+pub(crate) fn restore(session: &mut Session<PinkRuntime>, snapshot: &Snapshot) {
+    restore_chain(session, &snapshot.chain);
+    state::with_state(session, |state| {
+        state.active_cluster = snapshot.active_cluster;
+        state.idle_clusters = snapshot.idle_clusters.iter().cloned().collect();
+
+        // Mocks are set up by the test and seen nonces stay rejected, they outlive the snapshot.
+        let mut kept: BTreeMap<_, _> = std::mem::take(&mut state.idle_workers)
+            .into_iter()
+            .map(|(id, worker)| (id, (worker.ext_mocks, worker.query_nonces)))
+            .collect();
+        let active = std::mem::take(&mut state.worker);
+        kept.insert(state.active_worker, (active.ext_mocks, active.query_nonces));
+        let mut workers: BTreeMap<_, _> = snapshot
+            .workers
+            .iter()
+            .map(|(id, worker)| {
+                let (ext_mocks, query_nonces) = kept.remove(id).unwrap_or_default();
+                let worker = WorkerState {
+                    offchain: worker.offchain.clone(),
+                    identity: worker.identity.clone(),
+                    ext_mocks,
+                    query_nonces,
+                };
+                (*id, worker)
+            })
+            .collect();
+        state.worker = workers.remove(&snapshot.active_worker).unwrap_or_default();
+        state.active_worker = snapshot.active_worker;
+        state.idle_workers = workers;
+        state.worker_count = snapshot.worker_count;
+    });
+}
+
+pub(crate) fn take_chain(session: &mut Session<PinkRuntime>) -> ChainState {
+    session.sandbox().execute_with(|| ChainState {
+        storage: storage::top_pairs(),
+        contracts: storage::contract_trie_ids()
+            .into_iter()
+            .map(|trie_id| {
+                let pairs = storage::child_pairs(&trie_id);
+                (trie_id, pairs)
+            })
+            .collect(),
+    })
+}
+
+pub(crate) fn restore_chain(session: &mut Session<PinkRuntime>, chain: &ChainState) {
+    session.sandbox().execute_with(|| {
+        for trie_id in storage::contract_trie_ids() {
+            storage::clear_child(&trie_id);
+        }
+        storage::clear_top();
+        for (key, value) in &chain.storage {
+            unhashed::put_raw(key, value);
+        }
+        for (trie_id, pairs) in &chain.contracts {
+            for (key, value) in pairs {
+                storage::put_child(trie_id, key, value);
+            }
+        }
+    });
+}
@@ -0,0 +1,357 @@
+//! Off-chain state attached to a test session.
+//!
+//! `drink::session::Session` can not carry extra fields, so the state of a session is registered
+//! as an externalities extension of its sandbox. It lives outside of the storage and is dropped
+//! along with the session. While a call is executed through [`SessionExt`](crate::SessionExt),
+//! the state is made available to the runtime via `environmental`.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex};
+
+use drink::session::Session;
+use pallet_contracts::Determinism;
+use pink::CacheOp;
+use scale::{Decode, Encode};
+use sp_externalities::ExternalitiesExt as _;
+
+use crate::determinism::TxInputs;
+use crate::ext::{ExtHandler, ExtMocks, Faults};
+use crate::metadata::ContractMetadata;
+use crate::permission::PermissionDenial;
+use crate::snapshot::ChainState;
+use crate::trace::{CallTrace, TraceRecorder};
+use crate::types::{AccountId, BlockNumber, ClusterId, ExecMode, NondeterminismPolicy};
+use crate::worker::WorkerIdentity;
+use crate::PinkRuntime;
+
+pub(crate) type SharedState = Arc<Mutex<SessionState>>;
+
+sp_externalities::decl_extension! {
+    /// The state of the session owning the sandbox.
+    struct SessionHandle(SharedState);
+}
+
+#[derive(Default)]
+pub(crate) struct SessionState {
+    /// Debug buffers of all calls and instantiations, in execution order.
+    pub debug_messages: Vec<String>,
+    /// Logs emitted by contracts through the chain extension.
+    pub logs: Vec<ContractLog>,
+    /// Do not forward contract logs to the `log` crate.
+    pub quiet_logs: bool,
+    /// Hooks invoked after each block built by the session.
+    pub block_hooks: Vec<BlockHook>,
+    /// The worker serving queries.
+    pub worker: WorkerState,
+    pub active_worker: WorkerId,
+    /// The other workers of the cluster.
+    pub idle_workers: BTreeMap<WorkerId, WorkerState>,
+    pub worker_count: WorkerId,
+    /// The cluster whose state is in the sandbox.
+    pub active_cluster: ClusterId,
+    /// The on-chain state of the other clusters.
+    pub idle_clusters: BTreeMap<ClusterId, ChainState>,
+    /// Execute each transaction twice with different nondeterministic inputs first.
+    pub determinism_check: bool,
+    /// Inputs overriding the nondeterministic extension calls of a transaction.
+    pub tx_inputs: Option<TxInputs>,
+    /// Determinism of transactions, `Determinism::Enforced` if `None`.
+    pub tx_determinism: Option<Determinism>,
+    pub nondeterminism_policy: NondeterminismPolicy,
+    /// Nondeterministic extension calls made in transactions under a `Warn` or `Deny` policy.
+    pub nondeterministic_calls: Vec<NondeterministicCall>,
+    /// The call which failed the current transaction under the `Deny` policy.
+    pub denied_call: Option<NondeterministicCall>,
+    /// Record a call tree of every call and instantiation.
+    pub tracing: bool,
+    pub trace: TraceRecorder,
+    pub traces: Vec<CallTrace>,
+    /// Metadata used to decode traced calls, by contract address.
+    pub metadata: BTreeMap<AccountId, Arc<ContractMetadata>>,
+    /// Chain extension functions overridden by tests for all workers.
+    pub ext_mocks: ExtMocks,
+    pub faults: Faults,
+    /// Cache operations of the current transaction, applied to the workers when it is committed.
+    pub pending_cache_ops: Vec<(AccountId, CacheOp)>,
+    /// Privileged operations attempted without the required role.
+    pub permission_denials: Vec<PermissionDenial>,
+    /// The last denial of the latest call, explaining why it failed.
+    pub call_denial: Option<PermissionDenial>,
+    /// The origin and the contracts on the path of the current call.
+    pub call_stack: Vec<AccountId>,
+}
+
+/// Identifies a simulated worker of the session. Worker `0` exists from the start.
+pub type WorkerId = u32;
+
+/// State local to a simulated worker.
+#[derive(Default)]
+pub(crate) struct WorkerState {
+    /// State kept by the worker outside of the chain.
+    pub offchain: OffchainState,
+    /// The default keys are used if `None`.
+    pub identity: Option<WorkerIdentity>,
+    /// Chain extension functions overridden for this worker, taking precedence over the mocks of
+    /// the session.
+    pub ext_mocks: ExtMocks,
+    /// Nonces of the signed queries served, to reject replayed ones.
+    pub query_nonces: BTreeSet<[u8; 32]>,
+}
+
+impl SessionState {
+    pub fn add_worker(&mut self, identity: WorkerIdentity) -> WorkerId {
+        self.worker_count += 1;
+        let id = self.worker_count;
+        let worker = WorkerState {
+            identity: Some(identity),
+            ..Default::default()
+        };
+        self.idle_workers.insert(id, worker);
+        id
+    }
+
+    /// Make `id` the worker serving queries.
+    pub fn switch_worker(&mut self, id: WorkerId) -> Result<(), String> {
+        if id == self.active_worker {
+            return Ok(());
+        }
+        let worker = self
+            .idle_workers
+            .remove(&id)
+            .ok_or_else(|| format!("UnknownWorker: {id}"))?;
+        let previous = std::mem::replace(&mut self.worker, worker);
+        self.idle_workers.insert(self.active_worker, previous);
+        self.active_worker = id;
+        Ok(())
+    }
+
+    /// Remember how far the history of the session goes.
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            debug_messages: self.debug_messages.len(),
+            logs: self.logs.len(),
+            nondeterministic_calls: self.nondeterministic_calls.len(),
+            traces: self.traces.len(),
+            permission_denials: self.permission_denials.len(),
+            faults: self.faults.clone(),
+        }
+    }
+
+    /// Forget what was recorded since `checkpoint`, e.g. by a dry-run, and restore the fault
+    /// counters.
+    pub fn rollback(&mut self, checkpoint: Checkpoint) {
+        self.debug_messages.truncate(checkpoint.debug_messages);
+        self.logs.truncate(checkpoint.logs);
+        self.nondeterministic_calls
+            .truncate(checkpoint.nondeterministic_calls);
+        self.traces.truncate(checkpoint.traces);
+        self.permission_denials
+            .truncate(checkpoint.permission_denials);
+        self.denied_call = None;
+        self.pending_cache_ops.clear();
+        self.faults = checkpoint.faults;
+    }
+
+    /// Apply the cache operations of a committed transaction on every worker, like workers do
+    /// when they see the transaction in a block.
+    pub fn apply_cache_ops(&mut self) {
+        let ops = std::mem::take(&mut self.pending_cache_ops);
+        if ops.is_empty() {
+            return;
+        }
+        let workers = std::iter::once(&mut self.worker).chain(self.idle_workers.values_mut());
+        for worker in workers {
+            for (contract, op) in &ops {
+                worker.offchain.apply_cache_op(contract, op);
+            }
+        }
+    }
+
+    /// The mock of a chain extension function called by `contract` on the active worker.
+    pub fn ext_mock(&self, func_id: u32, contract: &AccountId) -> Option<ExtHandler> {
+        self.worker
+            .ext_mocks
+            .get(func_id, contract)
+            .or_else(|| self.ext_mocks.get(func_id, contract))
+    }
+}
+
+/// The length of the history of a session at some point, see [`SessionState::checkpoint`].
+pub(crate) struct Checkpoint {
+    debug_messages: usize,
+    logs: usize,
+    nondeterministic_calls: usize,
+    traces: usize,
+    permission_denials: usize,
+    /// Dry-runs must not count calls against the injected faults.
+    faults: Faults,
+}
+
+/// The off-chain state of a worker: contract cache, clock and randomness.
+#[derive(Debug, Clone, Default, Encode, Decode)]
+pub(crate) struct OffchainState {
+    pub cache: BTreeMap<AccountId, BTreeMap<Vec<u8>, CacheEntry>>,
+    /// Fixed value of `untrusted_millis_since_unix_epoch`. The system clock is used if `None`.
+    pub clock: Option<u64>,
+    /// Seeded randomness for `getrandom`. The OS randomness is used if `None`.
+    pub rng: Option<SeededRng>,
+}
+
+#[derive(Debug, Clone, Encode, Decode)]
+pub(crate) struct CacheEntry {
+    pub value: Vec<u8>,
+    /// Milliseconds since the unix epoch after which the entry is gone.
+    pub expire_at: Option<u64>,
+}
+
+impl OffchainState {
+    pub fn now_millis(&self) -> u64 {
+        self.clock.unwrap_or_else(|| {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .expect("System time before the unix epoch")
+                .as_millis() as u64
+        })
+    }
+
+    pub fn cache_get(&mut self, contract: &AccountId, key: &[u8]) -> Option<Vec<u8>> {
+        let now = self.now_millis();
+        let entries = self.cache.get_mut(contract)?;
+        let expired = entries.get(key)?.expire_at.is_some_and(|at| at <= now);
+        if expired {
+            entries.remove(key);
+            return None;
+        }
+        entries.get(key).map(|entry| entry.value.clone())
+    }
+
+    pub fn cache_set(&mut self, contract: &AccountId, key: Vec<u8>, value: Vec<u8>) {
+        self.cache.entry(contract.clone()).or_default().insert(
+            key,
+            CacheEntry {
+                value,
+                expire_at: None,
+            },
+        );
+    }
+
+    pub fn cache_set_expiration(&mut self, contract: &AccountId, key: &[u8], expire_secs: u64) {
+        let expire_at = self
+            .now_millis()
+            .saturating_add(expire_secs.saturating_mul(1000));
+        if let Some(entry) = self.cache.get_mut(contract).and_then(|c| c.get_mut(key)) {
+            entry.expire_at = Some(expire_at);
+        }
+    }
+
+    pub fn apply_cache_op(&mut self, contract: &AccountId, op: &CacheOp) {
+        match op {
+            CacheOp::Set { key, value } => self.cache_set(contract, key.clone(), value.clone()),
+            CacheOp::SetExpiration { key, expiration } => {
+                self.cache_set_expiration(contract, key, *expiration)
+            }
+            CacheOp::Remove { key } => {
+                self.cache_remove(contract, key);
+            }
+        }
+    }
+
+    pub fn cache_remove(&mut self, contract: &AccountId, key: &[u8]) -> Option<Vec<u8>> {
+        let value = self.cache_get(contract, key);
+        if let Some(entries) = self.cache.get_mut(contract) {
+            entries.remove(key);
+        }
+        value
+    }
+}
+
+/// A deterministic random stream: `blake2_256(seed ++ counter)` blocks.
+#[derive(Debug, Clone, Encode, Decode)]
+pub(crate) struct SeededRng {
+    seed: [u8; 32],
+    counter: u64,
+}
+
+impl SeededRng {
+    pub fn new(seed: [u8; 32]) -> Self {
+        Self { seed, counter: 0 }
+    }
+
+    pub fn fill(&mut self, length: usize) -> Vec<u8> {
+        let mut output = Vec::with_capacity(length);
+        while output.len() < length {
+            let block = sp_core::hashing::blake2_256(&(self.seed, self.counter).encode());
+            self.counter += 1;
+            let n = (length - output.len()).min(block.len());
+            output.extend_from_slice(&block[..n]);
+        }
+        output
+    }
+}
+
+pub(crate) type BlockHook = Arc<dyn Fn(&mut Session<PinkRuntime>, BlockNumber) + Send + Sync>;
+
+/// A log emitted by a contract via `pink::info!`, `pink::error!`, etc.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContractLog {
+    pub contract: AccountId,
+    pub level: log::Level,
+    pub message: String,
+    pub block_number: BlockNumber,
+    pub mode: ExecMode,
+}
+
+/// A nondeterministic extension call made by a contract in a transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NondeterministicCall {
+    pub contract: AccountId,
+    pub func_id: u32,
+    pub function: &'static str,
+    pub block_number: BlockNumber,
+}
+
+impl std::fmt::Display for NondeterministicCall {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "contract {} called `{}` (func_id {}) in a transaction",
+            self.contract, self.function, self.func_id
+        )
+    }
+}
+
+environmental::environmental!(current: SharedState);
+
+/// Get the state of the given session, allocating it on first use.
+pub(crate) fn of(session: &mut Session<PinkRuntime>) -> SharedState {
+    session.sandbox().execute_with(|| {
+        sp_externalities::with_externalities(|mut ext| {
+            if let Some(handle) = ext.extension::<SessionHandle>() {
+                return handle.0.clone();
+            }
+            let state = SharedState::default();
+            ext.register_extension(SessionHandle(state.clone()))
+                .expect("Sandbox externalities support extensions");
+            state
+        })
+        .expect("Sandbox externalities not set")
+    })
+}
+
+/// Access the state of the given session.
+pub(crate) fn with_state<T>(
+    session: &mut Session<PinkRuntime>,
+    f: impl FnOnce(&mut SessionState) -> T,
+) -> T {
+    f(&mut of(session).lock().expect("Session state poisoned"))
+}
+
+/// Run `f` with `state` as the state of the current session.
+pub(crate) fn using<T>(mut state: SharedState, f: impl FnOnce() -> T) -> T {
+    current::using(&mut state, f)
+}
+
+/// Access the state of the current session, if any.
+pub(crate) fn with<T>(f: impl FnOnce(&mut SessionState) -> T) -> Option<T> {
+    current::with(|state| f(&mut state.lock().expect("Session state poisoned")))
+}
@@ -0,0 +1,127 @@
+//! Raw access to the sandbox storage, including the child tries holding contract storage.
+//!
+//! Everything here must be called within the externalities of a sandbox.
+
+use frame_support::sp_io::{default_child_storage as child, storage};
+use scale::{Decode, Encode};
+use sp_core::hashing::{blake2_128, twox_128, twox_64};
+
+use crate::types::AccountId;
+
+pub(crate) type KeyValues = Vec<(Vec<u8>, Vec<u8>)>;
+
+/// Top-level keys starting with this prefix are managed by the child tries.
+const CHILD_STORAGE_PREFIX: &[u8] = b":child_storage:";
+
+fn contract_info_prefix() -> Vec<u8> {
+    let mut key = Vec::new();
+    key.extend(twox_128("Contracts".as_bytes()));
+    key.extend(twox_128("ContractInfoOf".as_bytes()));
+    key
+}
+
+fn contract_info_key(address: &AccountId) -> Vec<u8> {
+    let encoded = address.encode();
+    let mut key = contract_info_prefix();
+    key.extend(twox_64(&encoded));
+    key.extend(encoded);
+    key
+}
+
+/// The child trie id of a contract, read from `pallet_contracts::ContractInfoOf`.
+///
+/// `trie_id` is the first field of `ContractInfo`, so decoding a prefix is enough.
+pub(crate) fn contract_trie_id(address: &AccountId) -> Option<Vec<u8>> {
+    let info = frame_support::storage::unhashed::get_raw(&contract_info_key(address))?;
+    Vec::<u8>::decode(&mut &info[..]).ok()
+}
+
+/// The child trie ids of all instantiated contracts.
+pub(crate) fn contract_trie_ids() -> Vec<Vec<u8>> {
+    let prefix = contract_info_prefix();
+    top_pairs_with_prefix(&prefix)
+        .into_iter()
+        .filter_map(|(_, info)| Vec::<u8>::decode(&mut &info[..]).ok())
+        .collect()
+}
+
+/// All top-level key/value pairs whose key starts with `prefix`.
+pub(crate) fn top_pairs_with_prefix(prefix: &[u8]) -> KeyValues {
+    let mut pairs = Vec::new();
+    let mut key = prefix.to_vec();
+    while let Some(next) = storage::next_key(&key) {
+        if !next.starts_with(prefix) {
+            break;
+        }
+        if let Some(value) = frame_support::storage::unhashed::get_raw(&next) {
+            pairs.push((next.clone(), value));
+        }
+        key = next;
+    }
+    pairs
+}
+
+/// All top-level key/value pairs, excluding child trie roots.
+pub(crate) fn top_pairs() -> KeyValues {
+    top_pairs_with_prefix(&[])
+        .into_iter()
+        .filter(|(key, _)| !key.starts_with(CHILD_STORAGE_PREFIX))
+        .collect()
+}
+
+/// All key/value pairs of a child trie.
+pub(crate) fn child_pairs(trie_id: &[u8]) -> KeyValues {
+    let mut pairs = Vec::new();
+    let mut key = Vec::new();
+    while let Some(next) = child::next_key(trie_id, &key) {
+        if let Some(value) = child::get(trie_id, &next) {
+            pairs.push((next.clone(), value));
+        }
+        key = next;
+    }
+    pairs
+}
+
+/// The child trie key under which pallet-contracts stores the contract storage key `key`.
+///
+/// ink! always uses variable sized keys, which are hashed with `Blake2_128Concat`.
+pub(crate) fn contract_key(key: &[u8]) -> Vec<u8> {
+    blake2_128(key).iter().chain(key).copied().collect()
+}
+
+/// The contract storage key of a child trie key, the inverse of [`contract_key`].
+pub(crate) fn strip_contract_key(child_key: &[u8]) -> Option<&[u8]> {
+    child_key.get(16..)
+}
+
+pub(crate) fn get_child(trie_id: &[u8], key: &[u8]) -> Option<Vec<u8>> {
+    child::get(trie_id, key)
+}
+
+pub(crate) fn put_child(trie_id: &[u8], key: &[u8], value: &[u8]) {
+    child::set(trie_id, key, value);
+}
+
+pub(crate) fn remove_child(trie_id: &[u8], key: &[u8]) {
+    child::clear(trie_id, key);
+}
+
+pub(crate) fn clear_child(trie_id: &[u8]) {
+    for (key, _) in child_pairs(trie_id) {
+        child::clear(trie_id, &key);
+    }
+}
+
+/// Remove every top-level entry.
+pub(crate) fn clear_top() {
+    for (key, _) in top_pairs() {
+        storage::clear(&key);
+    }
+}
+
+/// Remove every top-level entry whose key starts with `prefix`.
+pub(crate) fn clear_prefix(prefix: &[u8]) {
+    for (key, _) in top_pairs_with_prefix(prefix) {
+        storage::clear(&key);
+    }
+}
@@ -0,0 +1,259 @@
+//! Call trees of contract executions.
+//!
+//! While tracing is enabled on a session, every contract call and instantiation, including the
+//! nested ones, and every chain extension call is recorded into a [`CallTrace`] tree.
+
+use std::fmt;
+
+use frame_support::weights::Weight;
+
+use crate::ext::ExtFunction;
+use crate::runtime::{RuntimeEvent, System};
+use crate::state::{self, SessionState};
+use crate::types::{AccountId, Balance};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallKind {
+    Call,
+    Instantiate,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TraceEntry {
+    Call(CallTrace),
+    /// A chain extension call.
+    Ext {
+        func_id: u32,
+    },
+}
+
+/// A contract call or instantiation together with everything it called.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallTrace {
+    pub kind: CallKind,
+    pub caller: AccountId,
+    pub callee: AccountId,
+    /// The value transferred to the callee with the call.
+    pub value: Balance,
+    pub input: Vec<u8>,
+    /// The returned data, `None` if the contract trapped.
+    pub output: Option<Vec<u8>>,
+    pub reverted: bool,
+    /// Gas consumed, only known for the outermost call. pallet-contracts does not expose the gas
+    /// meters of nested calls to the tracer.
+    pub gas_consumed: Option<Weight>,
+    /// The input rendered with the contract metadata, if registered.
+    pub decoded_input: Option<String>,
+    /// The output rendered with the contract metadata, if registered.
+    pub decoded_output: Option<String>,
+    /// Nested calls and chain extension calls, in execution order.
+    pub entries: Vec<TraceEntry>,
+}
+
+impl CallTrace {
+    pub fn selector(&self) -> Option<[u8; 4]> {
+        self.input.get(..4)?.try_into().ok()
+    }
+
+    /// All calls of the tree, depth first.
+    pub fn calls(&self) -> Vec<&CallTrace> {
+        let mut calls = vec![self];
+        for entry in &self.entries {
+            if let TraceEntry::Call(call) = entry {
+                calls.extend(call.calls());
+            }
+        }
+        calls
+    }
+
+    fn decode(&mut self, state: &SessionState) {
+        if let Some(metadata) = state.metadata.get(&self.callee) {
+            self.decoded_input = metadata.decode_input(self.kind, &self.input);
+            self.decoded_output = self
+                .output
+                .as_ref()
+                .and_then(|output| metadata.decode_output(self.kind, &self.input, output));
+        }
+        for entry in &mut self.entries {
+            if let TraceEntry::Call(call) = entry {
+                call.decode(state);
+            }
+        }
+    }
+
+    fn render(&self, f: &mut fmt::Formatter, depth: usize) -> fmt::Result {
+        let indent = "  ".repeat(depth);
+        let kind = match self.kind {
+            CallKind::Call => "call",
+            CallKind::Instantiate => "instantiate",
+        };
+        write!(
+            f,
+            "{indent}{kind} {} -> {} value: {}",
+            self.caller, self.callee, self.value
+        )?;
+        if let Some(gas) = self.gas_consumed {
+            write!(f, " gas: {}", gas.ref_time())?;
+        }
+        writeln!(f)?;
+        match &self.decoded_input {
+            Some(input) => writeln!(f, "{indent}  input: {input}")?,
+            None => writeln!(f, "{indent}  input: 0x{}", hex::encode(&self.input))?,
+        }
+        for entry in &self.entries {
+            match entry {
+                TraceEntry::Call(call) => call.render(f, depth + 1)?,
+                TraceEntry::Ext { func_id } => match ExtFunction::from_func_id(*func_id) {
+                    Some(function) => writeln!(f, "{indent}  ext: {function} (func_id {func_id})")?,
+                    None => writeln!(f, "{indent}  ext: func_id {func_id}")?,
+                },
+            }
+        }
+        let status = if self.reverted { "reverted" } else { "output" };
+        match (&self.decoded_output, &self.output) {
+            (Some(output), _) => writeln!(f, "{indent}  {status}: {output}"),
+            (None, Some(output)) => writeln!(f, "{indent}  {status}: 0x{}", hex::encode(output)),
+            (None, None) => writeln!(f, "{indent}  trapped"),
+        }
+    }
+}
+
+impl fmt::Display for CallTrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.render(f, 0)
+    }
+}
+
+/// The trace being recorded for the current call.
+#[derive(Default)]
+pub(crate) struct TraceRecorder {
+    /// Caller and value of the outermost call.
+    origin: Option<(AccountId, Balance)>,
+    /// Calls not returned yet, outermost first.
+    stack: Vec<CallTrace>,
+    root: Option<CallTrace>,
+    /// The number of events deposited when the previous call was entered or exited. Transfers
+    /// deposited since then belong to the next call.
+    event_mark: u32,
+}
+
+impl TraceRecorder {
+    fn enter(&mut self, kind: CallKind, callee: &AccountId, input: &[u8]) {
+        let (caller, value) = match self.stack.last() {
+            Some(parent) => (
+                parent.callee.clone(),
+                transferred_value(kind, callee, self.event_mark),
+            ),
+            None => self.origin.clone().unwrap_or((callee.clone(), 0)),
+        };
+        self.event_mark = System::event_count();
+        self.stack.push(CallTrace {
+            kind,
+            caller,
+            callee: callee.clone(),
+            value,
+            input: input.to_vec(),
+            output: None,
+            reverted: false,
+            gas_consumed: None,
+            decoded_input: None,
+            decoded_output: None,
+            entries: vec![],
+        });
+    }
+
+    /// Close the innermost open call, `output` is `None` if it trapped.
+    fn exit(&mut self, output: Option<&[u8]>, reverted: bool) {
+        let Some(mut call) = self.stack.pop() else {
+            return;
+        };
+        self.event_mark = System::event_count();
+        call.output = output.map(<[u8]>::to_vec);
+        call.reverted = reverted;
+        match self.stack.last_mut() {
+            Some(parent) => parent.entries.push(TraceEntry::Call(call)),
+            None => self.root = Some(call),
+        }
+    }
+}
+
+/// The value transferred to `callee` by a nested call, read from the transfer events deposited
+/// since `first_event`, as pallet-contracts does not pass it to the tracer.
+fn transferred_value(kind: CallKind, callee: &AccountId, first_event: u32) -> Balance {
+    let transfers = System::events()
+        .into_iter()
+        .skip(first_event as usize)
+        .filter_map(|record| match record.event {
+            RuntimeEvent::Balances(pallet_balances::Event::Transfer { to, amount, .. })
+                if &to == callee =>
+            {
+                Some(amount)
+            }
+            _ => None,
+        });
+    // Instantiations transfer the existential deposit from the origin before the value.
+    let skip = match kind {
+        CallKind::Call => 0,
+        CallKind::Instantiate => 1,
+    };
+    transfers.skip(skip).last().unwrap_or(0)
+}
+
+/// Start tracing a call or instantiation made by `origin`.
+pub(crate) fn begin(origin: &AccountId, value: Balance) {
+    state::with(|state| {
+        if state.tracing {
+            state.trace = TraceRecorder {
+                origin: Some((origin.clone(), value)),
+                event_mark: System::event_count(),
+                ..Default::default()
+            };
+        }
+    });
+}
+
+/// Finish tracing the outermost call and store its trace.
+pub(crate) fn end(gas_consumed: Weight) {
+    state::with(|state| {
+        if !state.tracing {
+            return;
+        }
+        // Calls which trapped never returned.
+        while !state.trace.stack.is_empty() {
+            state.trace.exit(None, false);
+        }
+        if let Some(mut trace) = state.trace.root.take() {
+            trace.gas_consumed = Some(gas_consumed);
+            trace.decode(state);
+            state.traces.push(trace);
+        }
+    });
+}
+
+pub(crate) fn enter(kind: CallKind, callee: &AccountId, input: &[u8]) {
+    state::with(|state| {
+        if state.tracing {
+            state.trace.enter(kind, callee, input);
+        }
+    });
+}
+
+/// Close the innermost open call, `output` is `None` if it trapped.
+pub(crate) fn exit(output: Option<&[u8]>, reverted: bool) {
+    state::with(|state| {
+        if state.tracing {
+            state.trace.exit(output, reverted);
+        }
+    });
+}
+
+pub(crate) fn ext_call(func_id: u32) {
+    state::with(|state| {
+        if !state.tracing {
+            return;
+        }
+        if let Some(call) = state.trace.stack.last_mut() {
+            call.entries.push(TraceEntry::Ext { func_id });
+        }
+    });
+}
@@ -3,6 +3,8 @@ use frame_support::sp_runtime;
 use sp_runtime::{traits::BlakeTwo256, AccountId32};
 
 pub type Hash = sp_core::H256;
+/// Identifies a cluster. The cluster created with a session has id zero.
+pub type ClusterId = Hash;
 pub type Hashing = BlakeTwo256;
 pub type AccountId = AccountId32;
 pub type Balance = u128;
@@ -12,6 +14,8 @@ pub type Nonce = u64;
 #[derive(Debug, Clone, PartialEq, Eq, Copy)]
 pub enum ExecMode {
     Query,
+    /// Dry-running a transaction to estimate its gas and storage deposit.
+    Estimating,
     Transaction,
 }
 
@@ -23,3 +27,18 @@ impl ExecMode {
         }
     }
 }
+
+/// What to do when a transaction calls an extension function whose result is not deterministic,
+/// such as `http_request`, `getrandom` or `untrusted_millis_since_unix_epoch`.
+///
+/// Workers answer such calls with stub values in transactions, which can hide bugs.
+#[derive(Debug, Clone, PartialEq, Eq, Copy, Default)]
+pub enum NondeterminismPolicy {
+    /// Silently return the stub values, like workers do.
+    #[default]
+    Allow,
+    /// Return the stub values, but log a warning and record the call.
+    Warn,
+    /// Fail the transaction.
+    Deny,
+}
@@ -0,0 +1,73 @@
+//! The identity of the simulated worker serving the session.
+
+use pink::types::sgx::SgxQuote;
+use pink::EcdhPublicKey;
+use scale::{Decode, Encode};
+use sp_core::{sr25519, Pair as _};
+
+/// The keys and attestation of a worker.
+///
+/// Queries see the ECDH public key via `worker_pubkey` and the quote via `worker_sgx_quote`.
+/// Transactions keep seeing the default values since they must not depend on the worker.
+#[derive(Clone)]
+pub struct WorkerIdentity {
+    ecdh: sr25519::Pair,
+    sgx_quote: Option<SgxQuote>,
+}
+
+impl WorkerIdentity {
+    /// Derive the ECDH keypair from a seed.
+    pub fn from_seed(seed: [u8; 32]) -> Self {
+        Self::from_pair(sr25519::Pair::from_seed(&seed))
+    }
+
+    pub fn from_pair(ecdh: sr25519::Pair) -> Self {
+        Self {
+            ecdh,
+            sgx_quote: None,
+        }
+    }
+
+    /// Attach a fixture quote returned by `worker_sgx_quote`.
+    pub fn with_sgx_quote(mut self, quote: SgxQuote) -> Self {
+        self.sgx_quote = Some(quote);
+        self
+    }
+
+    pub fn ecdh_pair(&self) -> &sr25519::Pair {
+        &self.ecdh
+    }
+
+    pub fn ecdh_public_key(&self) -> EcdhPublicKey {
+        self.ecdh.public().0
+    }
+
+    pub fn sgx_quote(&self) -> Option<&SgxQuote> {
+        self.sgx_quote.as_ref()
+    }
+}
+
+impl std::fmt::Debug for WorkerIdentity {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("WorkerIdentity")
+            .field("ecdh_public_key", &hex::encode(self.ecdh_public_key()))
+            .field("sgx_quote", &self.sgx_quote)
+            .finish()
+    }
+}
+
+/// Encoded with the secret ECDH key, so that snapshots can carry the identity.
+impl Encode for WorkerIdentity {
+    fn encode_to<T: scale::Output + ?Sized>(&self, dest: &mut T) {
+        (self.ecdh.to_raw_vec(), &self.sgx_quote).encode_to(dest)
+    }
+}
+
+impl Decode for WorkerIdentity {
+    fn decode<I: scale::Input>(input: &mut I) -> Result<Self, scale::Error> {
+        let (secret, sgx_quote) = <(Vec<u8>, Option<SgxQuote>)>::decode(input)?;
+        let ecdh =
+            sr25519::Pair::from_seed_slice(&secret).map_err(|_| "Invalid ECDH secret key")?;
+        Ok(Self { ecdh, sgx_quote })
+    }
+}
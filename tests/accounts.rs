@@ -0,0 +1,34 @@
+use drink::{runtime::Runtime, session::Session};
+use pink_drink::prelude::*;
+use pink_drink::{accounts, PinkRuntime};
+
+#[test]
+fn actors_are_scoped() {
+    let mut session = Session::<PinkRuntime>::new().expect("Failed to create session");
+    assert_eq!(session.actor(), PinkRuntime::default_actor());
+
+    let bob = accounts::bob().account_id();
+    let inner = session.as_actor(bob.clone(), |session| session.actor());
+    assert_eq!(inner, bob);
+    assert_eq!(session.actor(), PinkRuntime::default_actor());
+
+    session.set_actor(bob.clone());
+    assert_eq!(session.actor(), bob);
+}
+
+#[test]
+fn dev_accounts_can_be_funded_and_transfer() {
+    let mut session = Session::<PinkRuntime>::new().expect("Failed to create session");
+    let alice = accounts::alice();
+    let bob = accounts::bob();
+    let initial = session.free_balance(&alice);
+
+    session.fund(&alice, 100);
+    assert_eq!(session.free_balance(&alice), initial + 100);
+    session
+        .transfer(&alice, &bob, 40)
+        .expect("Failed to transfer");
+    assert_eq!(session.free_balance(&alice), initial + 60);
+    assert_eq!(session.free_balance(&bob), initial + 40);
+    assert!(session.transfer(&bob, &alice, u128::MAX).is_err());
+}
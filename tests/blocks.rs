@@ -0,0 +1,36 @@
+use std::sync::{Arc, Mutex};
+
+use drink::session::Session;
+use pink_drink::prelude::*;
+use pink_drink::PinkRuntime;
+
+#[test]
+fn a_new_session_starts_at_block_one() {
+    let mut session = Session::<PinkRuntime>::new().expect("Failed to create session");
+    assert_eq!(session.block_number(), 1);
+    assert!(session.parent_hash().is_zero());
+}
+
+#[test]
+fn hooks_run_for_each_block_of_a_new_session() {
+    let mut session = Session::<PinkRuntime>::new().expect("Failed to create session");
+    let seen = Arc::new(Mutex::new(vec![]));
+    let recorder = seen.clone();
+    session.on_block(move |session, number| {
+        assert_eq!(session.block_number(), number);
+        recorder.lock().unwrap().push(number);
+    });
+
+    assert_eq!(session.advance_blocks(3), 4);
+    assert_eq!(*seen.lock().unwrap(), vec![2, 3, 4]);
+    assert_eq!(session.parent_hash(), session.block_hash(3));
+    assert!(!session.block_hash(1).is_zero());
+}
+
+#[test]
+fn blocks_can_advance_the_timestamp() {
+    let mut session = Session::<PinkRuntime>::new().expect("Failed to create session");
+    session.set_timestamp(1_000);
+    assert_eq!(session.advance_blocks_with_time(2, 6_000), 3);
+    assert_eq!(session.timestamp(), 13_000);
+}
@@ -0,0 +1,106 @@
+use drink::session::Session;
+use pallet_contracts_primitives::StorageDeposit;
+use pink_drink::prelude::*;
+use pink_drink::{CallError, PinkRuntime};
+use scale::Encode;
+
+mod common;
+use common::{AccountId, Raw};
+
+/// A message reverting with `output` as the encoded return value.
+fn revert(contract: &AccountId, output: Vec<u8>) -> impl Callable<Ret = Result<u32, u8>> {
+    common::message(contract, common::REVERT, Raw(output))
+}
+
+/// The output of an ink! message returning `Err(err)`.
+fn contract_error(err: u8) -> Vec<u8> {
+    Ok::<Result<u32, u8>, ()>(Err(err)).encode()
+}
+
+#[test]
+fn contract_errors_are_decoded_from_reverted_calls() {
+    let mut session = Session::<PinkRuntime>::new().expect("Failed to create session");
+    let contract = common::deploy(&mut session);
+
+    let err = revert(&contract, contract_error(7))
+        .try_submit_tx(&mut session)
+        .expect_err("The call should fail");
+    assert_eq!(err, CallError::Contract(7));
+    let err = revert(&contract, contract_error(8))
+        .try_query(&mut session)
+        .expect_err("The call should fail");
+    assert_eq!(err.contract_error(), Some(8));
+}
+
+#[test]
+fn undecodable_reverts_are_runtime_errors() {
+    let mut session = Session::<PinkRuntime>::new().expect("Failed to create session");
+    let contract = common::deploy(&mut session);
+
+    let err = revert(&contract, vec![0xff])
+        .try_submit_tx(&mut session)
+        .expect_err("The call should fail");
+    match err {
+        CallError::Runtime(err) => assert_eq!(err.to_string(), "Contract reverted: 0xff"),
+        CallError::Contract(err) => panic!("Unexpected contract error: {err}"),
+    }
+}
+
+#[test]
+fn detailed_results_report_gas_deposit_and_debug_messages() {
+    let mut session = Session::<PinkRuntime>::new().expect("Failed to create session");
+    let contract = common::deploy(&mut session);
+
+    let result = common::message::<_, ()>(&contract, common::DEBUG, Raw(b"hello".to_vec()))
+        .submit_tx_detailed(&mut session)
+        .expect("Failed to submit");
+    assert_eq!(result.debug_message, "hello");
+    assert!(result.gas_consumed.ref_time() > 0);
+    assert!(result.gas_required.ref_time() >= result.gas_consumed.ref_time());
+
+    let result = common::message::<_, ()>(&contract, common::STORE, Raw(b"value".to_vec()))
+        .submit_tx_detailed(&mut session)
+        .expect("Failed to submit");
+    assert!(matches!(result.storage_deposit, StorageDeposit::Charge(amount) if amount > 0));
+
+    let result = common::message::<_, ()>(&contract, common::DEBUG, Raw(b"query".to_vec()))
+        .query_detailed(&mut session)
+        .expect("Failed to query");
+    assert_eq!(result.debug_message, "query");
+    assert_eq!(session.take_debug_messages(), vec!["hello", "query"]);
+    assert!(session.debug_messages().is_empty());
+}
+
+#[test]
+fn estimated_transactions_are_submitted_with_the_estimated_limits() {
+    let mut session = Session::<PinkRuntime>::new().expect("Failed to create session");
+    let contract = common::deploy(&mut session);
+
+    // The estimate covers the gas and the storage deposit without any margin.
+    common::message::<_, ()>(&contract, common::STORE, Raw(b"value".to_vec()))
+        .submit_tx_estimated(&mut session, 0)
+        .expect("Failed to submit");
+    assert_eq!(
+        session
+            .read_storage(&contract, &[0; 4])
+            .expect("Contract not found"),
+        Some(b"value".to_vec())
+    );
+
+    // Only the submitted transaction shows up, not the dry-run.
+    common::message::<_, ()>(&contract, common::DEBUG, Raw(b"hello".to_vec()))
+        .submit_tx_estimated(&mut session, 10)
+        .expect("Failed to submit");
+    assert_eq!(session.take_debug_messages(), vec!["hello"]);
+}
+
+#[test]
+fn reverted_estimates_are_not_submitted() {
+    let mut session = Session::<PinkRuntime>::new().expect("Failed to create session");
+    let contract = common::deploy(&mut session);
+
+    let result = revert(&contract, contract_error(7))
+        .submit_tx_estimated(&mut session, 10)
+        .expect("Failed to estimate");
+    assert_eq!(result, Err(7));
+}
@@ -0,0 +1,81 @@
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use drink::session::Session;
+use pink_drink::prelude::*;
+use pink_drink::{accounts, ClusterId, PinkRuntime};
+use sp_core::crypto::AccountId32;
+
+fn system_contract(session: &mut Session<PinkRuntime>) -> AccountId32 {
+    session
+        .query(PinkRuntime::system_contract)
+        .expect("System contract not found")
+}
+
+#[test]
+fn clusters_share_the_chain() {
+    let mut session = Session::<PinkRuntime>::new().expect("Failed to create session");
+    let cluster = ClusterId::repeat_byte(1);
+    session
+        .create_cluster(cluster)
+        .expect("Failed to create cluster");
+    let system = system_contract(&mut session);
+    let alice = accounts::alice().account_id();
+    let bob = accounts::bob().account_id();
+
+    let block = session.advance_blocks(2);
+    let balance = session.free_balance(alice.clone());
+    session
+        .select_cluster(cluster)
+        .expect("Failed to select cluster");
+    assert_eq!(session.active_cluster(), cluster);
+    assert_ne!(system_contract(&mut session), system);
+    assert_eq!(session.block_number(), block);
+    assert_eq!(session.free_balance(alice.clone()), balance);
+
+    session
+        .transfer(alice.clone(), bob, 1_000)
+        .expect("Failed to transfer");
+    session
+        .select_cluster(ClusterId::zero())
+        .expect("Failed to select cluster");
+    assert_eq!(system_contract(&mut session), system);
+    assert_eq!(session.free_balance(alice), balance - 1_000);
+}
+
+#[test]
+fn snapshots_keep_idle_clusters() {
+    let mut session = Session::<PinkRuntime>::new().expect("Failed to create session");
+    let cluster = ClusterId::repeat_byte(1);
+    session
+        .create_cluster(cluster)
+        .expect("Failed to create cluster");
+    let idle_system = session
+        .with_cluster(cluster, system_contract)
+        .expect("Failed to select cluster");
+
+    let mut restored = session
+        .snapshot()
+        .new_session()
+        .expect("Failed to restore snapshot");
+    let system = restored
+        .with_cluster(cluster, system_contract)
+        .expect("Failed to select cluster");
+    assert_eq!(system, idle_system);
+}
+
+#[test]
+fn with_cluster_switches_back_after_a_panic() {
+    let mut session = Session::<PinkRuntime>::new().expect("Failed to create session");
+    let cluster = ClusterId::repeat_byte(1);
+    session
+        .create_cluster(cluster)
+        .expect("Failed to create cluster");
+    let system = system_contract(&mut session);
+
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        session.with_cluster(cluster, |_| panic!("boom"))
+    }));
+    assert!(result.is_err());
+    assert_eq!(session.active_cluster(), ClusterId::zero());
+    assert_eq!(system_contract(&mut session), system);
+}
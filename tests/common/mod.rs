@@ -0,0 +1,165 @@
+//! A contract driven entirely by its input, to exercise the runtime without ink! artifacts.
+//!
+//! The first four bytes of the input, little endian, select what the contract does with the rest:
+//! one of the commands below, or else the `func_id` of a pink chain extension function to call
+//! with the rest as its encoded arguments. The output of messages is wrapped in an `Ok` like
+//! ink! does, so they can be called through `Callable`.
+#![allow(dead_code)]
+
+use drink::{runtime::AccountIdFor, session::Session};
+use ink::env::{
+    call::{build_call, ExecutionInput, Selector},
+    DefaultEnvironment,
+};
+use pink_drink::prelude::*;
+use pink_drink::{ExtFunction, PinkRuntime};
+use scale::{Decode, Encode};
+
+pub type AccountId = AccountIdFor<PinkRuntime>;
+
+/// Emit the rest of the input as the data of a contract event.
+pub const EMIT: u32 = 0xffff_0001;
+/// Write the rest of the input to the debug buffer.
+pub const DEBUG: u32 = 0xffff_0002;
+/// Revert with the rest of the input as the output.
+pub const REVERT: u32 = 0xffff_0003;
+/// Store the rest of the input under the ink! root key `0x00000000`.
+pub const STORE: u32 = 0xffff_0004;
+/// Call the contract given by the next 32 bytes, transferring the `u128` after them, with the
+/// rest of the input, and return its output.
+pub const CALL: u32 = 0xffff_0005;
+/// Return the caller of the contract.
+pub const CALLER: u32 = 0xffff_0006;
+
+const WAT: &str = r#"
+(module
+  (import "seal0" "seal_input" (func $input (param i32 i32)))
+  (import "seal0" "seal_return" (func $return (param i32 i32 i32)))
+  (import "seal0" "seal_deposit_event" (func $deposit_event (param i32 i32 i32 i32)))
+  (import "seal0" "seal_debug_message" (func $debug_message (param i32 i32) (result i32)))
+  (import "seal0" "seal_call_chain_extension"
+    (func $call_chain_extension (param i32 i32 i32 i32 i32) (result i32)))
+  (import "seal2" "set_storage" (func $set_storage (param i32 i32 i32 i32) (result i32)))
+  (import "seal1" "seal_call"
+    (func $call (param i32 i32 i64 i32 i32 i32 i32 i32) (result i32)))
+  (import "seal0" "seal_caller" (func $caller (param i32 i32)))
+  (import "env" "memory" (memory 1 16))
+
+  ;; 0x0000: input length, 0x0004: output length, 0x0100: input, 0x4100: output,
+  ;; 0x8100: the root storage key
+  (func (export "deploy"))
+  (func (export "call")
+    (local $command i32)
+    (local $len i32)
+    (i32.store (i32.const 0) (i32.const 0x4000))
+    (call $input (i32.const 0x100) (i32.const 0))
+    (local.set $command (i32.load (i32.const 0x100)))
+    (local.set $len (i32.sub (i32.load (i32.const 0)) (i32.const 4)))
+    (block $done
+      (if (i32.eq (local.get $command) (i32.const 0xffff0001))
+        (then
+          (call $deposit_event (i32.const 0) (i32.const 0) (i32.const 0x104) (local.get $len))
+          (br $done)))
+      (if (i32.eq (local.get $command) (i32.const 0xffff0002))
+        (then
+          (drop (call $debug_message (i32.const 0x104) (local.get $len)))
+          (br $done)))
+      (if (i32.eq (local.get $command) (i32.const 0xffff0003))
+        (then
+          (call $return (i32.const 1) (i32.const 0x104) (local.get $len))))
+      (if (i32.eq (local.get $command) (i32.const 0xffff0004))
+        (then
+          (drop (call $set_storage
+            (i32.const 0x8100) (i32.const 4) (i32.const 0x104) (local.get $len)))
+          (br $done)))
+      (if (i32.eq (local.get $command) (i32.const 0xffff0005))
+        (then
+          (i32.store (i32.const 4) (i32.const 0x4000))
+          (drop (call $call
+            (i32.const 0)
+            (i32.const 0x104)
+            (i64.const 0)
+            (i32.const 0x124)
+            (i32.const 0x134)
+            (i32.sub (local.get $len) (i32.const 48))
+            (i32.const 0x4100)
+            (i32.const 4)))
+          (call $return (i32.const 0) (i32.const 0x4100) (i32.load (i32.const 4)))))
+      (if (i32.eq (local.get $command) (i32.const 0xffff0006))
+        (then
+          (i32.store (i32.const 4) (i32.const 32))
+          (call $caller (i32.const 0x4101) (i32.const 4))
+          (call $return (i32.const 0) (i32.const 0x4100) (i32.const 33))))
+      (i32.store (i32.const 4) (i32.const 0x3fff))
+      (drop (call $call_chain_extension
+        (local.get $command) (i32.const 0x104) (local.get $len) (i32.const 0x4101) (i32.const 4)))
+      (call $return
+        (i32.const 0) (i32.const 0x4100) (i32.add (i32.load (i32.const 4)) (i32.const 1))))
+    (call $return (i32.const 0) (i32.const 0x4100) (i32.const 1))))
+"#;
+
+pub fn wasm() -> Vec<u8> {
+    wat::parse_str(WAT).expect("Failed to assemble the test contract")
+}
+
+/// Upload the test contract with enforced determinism and instantiate it as the session actor.
+pub fn deploy(session: &mut Session<PinkRuntime>) -> AccountId {
+    deploy_with_salt(session, b"")
+}
+
+pub fn deploy_with_salt(session: &mut Session<PinkRuntime>, salt: &[u8]) -> AccountId {
+    let actor = session.actor();
+    session
+        .tx(|| {
+            let code_hash = PinkRuntime::upload_code(actor.clone(), wasm(), true)?;
+            PinkRuntime::instantiate(actor, 0, u64::MAX, None, code_hash, vec![], salt.to_vec())
+        })
+        .expect("Failed to deploy the test contract")
+}
+
+/// A message of the test contract running `command` with `args`.
+pub fn message<Args: Encode, Ret: Decode>(
+    contract: &AccountId,
+    command: u32,
+    args: Args,
+) -> impl Callable<Ret = Ret> {
+    let callee: [u8; 32] = contract.clone().into();
+    build_call::<DefaultEnvironment>()
+        .call(callee.into())
+        .exec_input(ExecutionInput::new(Selector::new(command.to_le_bytes())).push_arg(args))
+        .returns::<Ret>()
+}
+
+/// Make the test contract call `function` with `args`, returning what the function returns.
+pub fn ext<Args: Encode, Ret: Decode>(
+    contract: &AccountId,
+    function: ExtFunction,
+    args: Args,
+) -> impl Callable<Ret = Ret> {
+    message(contract, function.func_id(), args)
+}
+
+/// Make `contract` call `callee` with `value` and the input of `command` with `args`.
+pub fn nested<Args: Encode, Ret: Decode>(
+    contract: &AccountId,
+    callee: &AccountId,
+    value: u128,
+    command: u32,
+    args: Args,
+) -> impl Callable<Ret = Ret> {
+    let input = (command.to_le_bytes(), args).encode();
+    message(contract, CALL, (callee, value, Raw(input)))
+}
+
+/// Bytes encoded as is, without a length prefix.
+pub struct Raw(pub Vec<u8>);
+
+impl Encode for Raw {
+    fn size_hint(&self) -> usize {
+        self.0.len()
+    }
+
+    fn encode_to<T: scale::Output + ?Sized>(&self, dest: &mut T) {
+        dest.write(&self.0);
+    }
+}
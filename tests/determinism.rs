@@ -0,0 +1,139 @@
+use drink::session::Session;
+use pink_drink::prelude::*;
+use pink_drink::{ContractDump, Determinism, ExtFunction, NondeterminismPolicy, PinkRuntime};
+
+mod common;
+use common::{AccountId, Raw};
+
+fn getrandom(contract: &AccountId) -> impl Callable<Ret = Vec<u8>> {
+    common::ext(contract, ExtFunction::Getrandom, 32u8)
+}
+
+#[test]
+fn transactions_depending_on_randomness_fail_the_check() {
+    let mut session = Session::<PinkRuntime>::new().expect("Failed to create session");
+    let contract = common::deploy(&mut session);
+    session.set_determinism_check(true);
+
+    let err = getrandom(&contract)
+        .submit_tx(&mut session)
+        .expect_err("The check should fail");
+    assert!(
+        err.to_string()
+            .starts_with("NondeterministicTransaction: return data differ"),
+        "{err}"
+    );
+
+    session.set_determinism_check(false);
+    assert_eq!(getrandom(&contract).submit_tx(&mut session), Ok(vec![]));
+}
+
+#[test]
+fn deterministic_transactions_pass_the_check_once() {
+    let mut session = Session::<PinkRuntime>::new().expect("Failed to create session");
+    let contract = common::deploy(&mut session);
+    session.set_determinism_check(true);
+
+    common::message::<_, ()>(&contract, common::DEBUG, Raw(b"hello".to_vec()))
+        .submit_tx(&mut session)
+        .expect("Failed to submit");
+    // The checking runs do not show up in the session history.
+    assert_eq!(session.debug_messages(), vec!["hello"]);
+}
+
+#[test]
+fn nondeterministic_calls_are_recorded_under_the_warn_policy() {
+    let mut session = Session::<PinkRuntime>::new().expect("Failed to create session");
+    let contract = common::deploy(&mut session);
+
+    getrandom(&contract)
+        .submit_tx(&mut session)
+        .expect("Failed to submit");
+    assert!(session.nondeterministic_calls().is_empty());
+
+    session.set_nondeterminism_policy(NondeterminismPolicy::Warn);
+    getrandom(&contract)
+        .submit_tx(&mut session)
+        .expect("Failed to submit");
+    getrandom(&contract)
+        .query(&mut session)
+        .expect("Failed to query");
+    let calls = session.nondeterministic_calls();
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].contract, contract);
+    assert_eq!(calls[0].function, "getrandom");
+    assert_eq!(calls[0].func_id, ExtFunction::Getrandom.func_id());
+}
+
+#[test]
+fn nondeterministic_calls_fail_transactions_under_the_deny_policy() {
+    let mut session = Session::<PinkRuntime>::new().expect("Failed to create session");
+    let contract = common::deploy(&mut session);
+    session.set_nondeterminism_policy(NondeterminismPolicy::Deny);
+
+    let err = getrandom(&contract)
+        .submit_tx(&mut session)
+        .expect_err("The call should be denied");
+    assert!(
+        err.to_string().starts_with("NondeterministicExtCall: "),
+        "{err}"
+    );
+    assert_eq!(session.nondeterministic_calls().len(), 1);
+
+    // Queries may call nondeterministic functions.
+    assert_eq!(
+        getrandom(&contract)
+            .query(&mut session)
+            .expect("Failed to query")
+            .len(),
+        32
+    );
+}
+
+/// Import the test contract with its code uploaded with relaxed determinism.
+fn import_relaxed(session: &mut Session<PinkRuntime>) -> AccountId {
+    let deployer: [u8; 32] = session.actor().into();
+    let dump = serde_json::json!({
+        "deployer": format!("0x{}", hex::encode(deployer)),
+        "code": hex::encode(common::wasm()),
+        "determinism": "relaxed",
+        "storage": [],
+    });
+    let dump = ContractDump::from_json(&dump.to_string()).expect("Failed to parse dump");
+    session.import_contract(&dump).expect("Failed to import")
+}
+
+#[test]
+fn relaxed_code_only_runs_in_queries_unless_transactions_are_relaxed() {
+    let mut session = Session::<PinkRuntime>::new().expect("Failed to create session");
+    let contract = import_relaxed(&mut session);
+    let store =
+        |value: &[u8]| common::message::<_, ()>(&contract, common::STORE, Raw(value.to_vec()));
+
+    let err = store(b"tx")
+        .submit_tx(&mut session)
+        .expect_err("Relaxed code should not run in transactions");
+    assert!(
+        err.to_string().starts_with(
+            "Failed to execute call: Indeterministic: code uploaded with `Determinism::Relaxed`"
+        ),
+        "{err}"
+    );
+    store(b"query")
+        .query(&mut session)
+        .expect("Failed to query");
+    store(b"relaxed")
+        .submit_tx_with_determinism(&mut session, Determinism::Relaxed)
+        .expect("Failed to submit");
+
+    session.set_tx_determinism(Determinism::Relaxed);
+    store(b"session")
+        .submit_tx(&mut session)
+        .expect("Failed to submit");
+    assert_eq!(
+        session
+            .read_storage(&contract, &[0; 4])
+            .expect("Contract not found"),
+        Some(b"session".to_vec())
+    );
+}
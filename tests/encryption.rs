@@ -0,0 +1,45 @@
+use pink_drink::EncryptedData;
+use sp_core::{sr25519, Pair as _};
+
+fn pairs() -> (sr25519::Pair, sr25519::Pair) {
+    (
+        sr25519::Pair::from_seed(&[1u8; 32]),
+        sr25519::Pair::from_seed(&[2u8; 32]),
+    )
+}
+
+#[test]
+fn sealed_data_opens_on_both_sides() {
+    let (client, worker) = pairs();
+    let request = EncryptedData::seal(&client, &worker.public().0, b"request").unwrap();
+    assert_eq!(request.open(&worker).unwrap(), b"request");
+
+    let response = EncryptedData::seal(&worker, &request.pubkey, b"response").unwrap();
+    assert_eq!(response.open(&client).unwrap(), b"response");
+}
+
+#[test]
+fn each_message_has_its_own_iv() {
+    let (client, worker) = pairs();
+    let first = EncryptedData::seal(&client, &worker.public().0, b"same").unwrap();
+    let second = EncryptedData::seal(&client, &worker.public().0, b"same").unwrap();
+    assert_ne!(first.iv, second.iv);
+    assert_ne!(first.data, second.data);
+}
+
+#[test]
+fn tampered_data_does_not_open() {
+    let (client, worker) = pairs();
+    let sealed = EncryptedData::seal(&client, &worker.public().0, b"request").unwrap();
+
+    let mut tampered = sealed.clone();
+    tampered.data[0] ^= 1;
+    assert!(tampered.open(&worker).is_err());
+
+    let mut tampered = sealed.clone();
+    tampered.iv[0] ^= 1;
+    assert!(tampered.open(&worker).is_err());
+
+    let stranger = sr25519::Pair::from_seed(&[3u8; 32]);
+    assert!(sealed.open(&stranger).is_err());
+}
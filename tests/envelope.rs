@@ -0,0 +1,155 @@
+use drink::session::Session;
+use pink_drink::prelude::*;
+use pink_drink::{
+    accounts, Certificate, CertifiedSigner, ContractQuery, EnvelopeSigner, PinkRuntime,
+    QueryEnvelope, SignatureType, WrapBytes,
+};
+use scale::{Decode, Encode};
+use sp_core::{crypto::AccountId32, ecdsa, ed25519, Pair as _};
+
+mod common;
+use common::AccountId;
+
+fn contract() -> AccountId32 {
+    AccountId32::new([7u8; 32])
+}
+
+fn origin(envelope: &QueryEnvelope) -> Option<AccountId32> {
+    envelope.verify(1).ok().map(|(origin, _)| origin)
+}
+
+/// Decode the query of `envelope`, change it with `f` and encode it back, keeping the signature.
+fn tamper(envelope: &QueryEnvelope, f: impl FnOnce(&mut ContractQuery)) -> QueryEnvelope {
+    let mut query = ContractQuery::decode(&mut &envelope.encoded_query[..]).expect("Bad query");
+    f(&mut query);
+    QueryEnvelope {
+        encoded_query: query.encode(),
+        signature: envelope.signature.clone(),
+    }
+}
+
+#[test]
+fn queries_are_from_the_signer() {
+    let alice = accounts::alice();
+    let envelope = QueryEnvelope::new(contract(), vec![1, 2, 3, 4], &alice);
+    let (origin, query) = envelope.verify(1).expect("Failed to verify");
+    assert_eq!(origin, alice.account_id());
+    assert_eq!(query.head.id, contract());
+    assert_eq!(query.data, vec![1, 2, 3, 4]);
+
+    let pair = ed25519::Pair::from_seed(&[2u8; 32]);
+    let envelope = QueryEnvelope::new(contract(), vec![], &pair);
+    assert_eq!(origin(&envelope), Some(pair.public().into()));
+}
+
+#[test]
+fn ecdsa_origin_is_the_hashed_public_key() {
+    let pair = ecdsa::Pair::from_seed(&[3u8; 32]);
+    let envelope = QueryEnvelope::new(contract(), vec![1, 2, 3, 4], &pair);
+    let expected = AccountId32::from(sp_core::hashing::blake2_256(pair.public().as_ref()));
+    assert_eq!(origin(&envelope), Some(expected));
+}
+
+#[test]
+fn wrapped_bytes_are_signed_like_wallets_do() {
+    let alice = accounts::alice();
+    let envelope = QueryEnvelope::new(contract(), vec![1, 2, 3, 4], &WrapBytes(alice.clone()));
+    assert_eq!(
+        envelope.signature.signature_type,
+        SignatureType::Sr25519WrapBytes
+    );
+    assert_eq!(origin(&envelope), Some(alice.account_id()));
+
+    let mut unwrapped = envelope;
+    unwrapped.signature.signature_type = SignatureType::Sr25519;
+    assert_eq!(origin(&unwrapped), None);
+}
+
+#[test]
+fn certified_keys_sign_for_the_root_of_the_chain() {
+    let alice = accounts::alice();
+    let signer = CertifiedSigner::issue(&WrapBytes(alice.clone()), 10);
+    let envelope = QueryEnvelope::new(contract(), vec![1, 2, 3, 4], &signer);
+
+    let chain = envelope
+        .signature
+        .verify(&envelope.encoded_query, 10)
+        .expect("Failed to verify");
+    let key = AccountId32::new(signer.public_key().try_into().expect("Bad key"));
+    assert_eq!(chain, vec![alice.account_id(), key]);
+    assert_eq!(origin(&envelope), Some(alice.account_id()));
+
+    let err = envelope
+        .verify(11)
+        .expect_err("The certificate should expire");
+    assert!(err.to_string().starts_with("CertificateExpired: "), "{err}");
+}
+
+#[test]
+fn tampered_queries_are_rejected() {
+    let alice = accounts::alice();
+    let envelope = QueryEnvelope::new(contract(), vec![1, 2, 3, 4], &alice);
+
+    let tampered = tamper(&envelope, |query| query.data.push(5));
+    assert!(tampered.verify(1).is_err());
+    let redirected = tamper(&envelope, |query| {
+        query.head.id = AccountId32::new([8u8; 32])
+    });
+    assert!(redirected.verify(1).is_err());
+
+    // Claiming the signature of another account.
+    let mut impersonated = envelope;
+    impersonated.signature.signed_by =
+        Some(Box::new(Certificate::root(accounts::bob().public_key())));
+    assert!(impersonated.verify(1).is_err());
+
+    // A certificate issued by alice can not be reattached to the key of bob.
+    let signer = CertifiedSigner::issue(&alice, 10);
+    let mut stolen = QueryEnvelope::new(contract(), vec![], &accounts::bob());
+    let mut certificate = signer.certificate();
+    certificate.encoded_body = stolen
+        .signature
+        .signed_by
+        .as_ref()
+        .unwrap()
+        .encoded_body
+        .clone();
+    stolen.signature.signed_by = Some(Box::new(certificate));
+    assert!(stolen.verify(1).is_err());
+}
+
+fn caller(contract: &AccountId) -> impl Callable<Ret = AccountId> {
+    common::message(contract, common::CALLER, ())
+}
+
+#[test]
+fn signed_queries_run_as_their_origin_once() {
+    let mut session = Session::<PinkRuntime>::new().expect("Failed to create session");
+    let contract = common::deploy(&mut session);
+    let alice = accounts::alice();
+
+    let signer = CertifiedSigner::issue(&alice, 3);
+    assert_eq!(
+        caller(&contract).query_as_signer(&mut session, &signer),
+        Ok(alice.account_id())
+    );
+
+    let envelope = caller(&contract).envelope(&signer);
+    assert_eq!(
+        session.query_envelope::<AccountId>(&envelope),
+        Ok(alice.account_id())
+    );
+    let err = session
+        .query_envelope::<AccountId>(&envelope)
+        .expect_err("Replayed queries should be rejected");
+    assert!(
+        err.to_string().starts_with("DuplicateQueryNonce: "),
+        "{err}"
+    );
+
+    session.advance_blocks(3);
+    let err = caller(&contract)
+        .query_as_signer(&mut session, &signer)
+        .expect_err("The certificate should expire");
+    assert!(err.to_string().starts_with("CertificateExpired: "), "{err}");
+}
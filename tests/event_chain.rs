@@ -0,0 +1,69 @@
+use drink::session::Session;
+use pink::chain_extension::StorageQuotaExceeded;
+use pink_drink::prelude::*;
+use pink_drink::{ExtFunction, PinkRuntime};
+
+mod common;
+use common::Raw;
+
+#[test]
+fn the_first_transaction_of_a_session_produces_an_event_block() {
+    let mut session = Session::<PinkRuntime>::new().expect("Failed to create session");
+    let contract = common::deploy(&mut session);
+    assert!(session.event_blocks().is_empty());
+
+    common::message::<_, ()>(&contract, common::EMIT, Raw(b"hello".to_vec()))
+        .submit_tx(&mut session)
+        .expect("Failed to emit event");
+
+    let blocks = session.event_blocks();
+    assert_eq!(blocks.len(), 1);
+    let block = &blocks[0];
+    assert_eq!(block.number, 0);
+    assert!(block.parent_hash.is_zero());
+    assert_eq!(block.block_number, 1);
+    assert_eq!(block.entry_contract, contract);
+    assert_eq!(block.events.len(), 1);
+    assert_eq!(block.events[0].contract, contract);
+    assert_eq!(block.events[0].data, b"hello");
+    assert_eq!(session.event_chain_head(), (1, block.hash()));
+}
+
+#[test]
+fn event_blocks_link_to_their_parent() {
+    let mut session = Session::<PinkRuntime>::new().expect("Failed to create session");
+    let contract = common::deploy(&mut session);
+    for data in [b"one", b"two"] {
+        common::message::<_, ()>(&contract, common::EMIT, Raw(data.to_vec()))
+            .submit_tx(&mut session)
+            .expect("Failed to emit event");
+        session.advance_blocks(1);
+    }
+
+    let blocks = session.event_blocks();
+    assert_eq!(blocks.len(), 2);
+    assert_eq!(blocks[1].number, 1);
+    assert_eq!(blocks[1].parent_hash, blocks[0].hash());
+    assert_eq!(blocks[1].block_number, 2);
+}
+
+#[test]
+fn the_first_transaction_of_a_session_commits_its_cache_ops() {
+    let mut session = Session::<PinkRuntime>::new().expect("Failed to create session");
+    let contract = common::deploy(&mut session);
+
+    common::ext::<_, Result<(), StorageQuotaExceeded>>(
+        &contract,
+        ExtFunction::CacheSet,
+        (b"key".to_vec(), b"value".to_vec()),
+    )
+    .submit_tx(&mut session)
+    .expect("Failed to call cache_set")
+    .expect("Cache quota exceeded");
+
+    let value =
+        common::ext::<_, Option<Vec<u8>>>(&contract, ExtFunction::CacheGet, b"key".to_vec())
+            .query(&mut session)
+            .expect("Failed to call cache_get");
+    assert_eq!(value, Some(b"value".to_vec()));
+}
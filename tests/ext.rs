@@ -0,0 +1,16 @@
+use pink_drink::ExtFunction;
+
+#[test]
+fn func_ids_round_trip() {
+    for function in ExtFunction::ALL {
+        assert_eq!(
+            ExtFunction::from_func_id(function.func_id()),
+            Some(function)
+        );
+    }
+    assert_eq!(
+        ExtFunction::HttpRequest.func_id(),
+        pink::chain_extension::func_ids::HTTP_REQUEST
+    );
+    assert_eq!(ExtFunction::from_func_id(0), None);
+}
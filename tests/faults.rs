@@ -0,0 +1,58 @@
+use drink::{runtime::Runtime, session::Session};
+use pink_drink::prelude::*;
+use pink_drink::{ExtFunction, Fault, FaultInjection, PinkRuntime};
+use scale::{Decode, Encode};
+
+/// Call `code_exists` of the system contract, which calls the `code_exists` chain extension.
+fn code_exists(session: &mut Session<PinkRuntime>, estimate: bool) -> Option<bool> {
+    let selector = sp_core::hashing::blake2_256(b"pink_system::System::code_exists");
+    // `CodeType::Ink`
+    let input = (&selector[..4], [0u8; 32], 0u8).encode();
+    let call = || {
+        let system = PinkRuntime::system_contract().expect("System contract not found");
+        PinkRuntime::bare_call(
+            PinkRuntime::default_actor(),
+            system,
+            0,
+            u64::MAX,
+            None,
+            input,
+            true,
+        )
+    };
+    let result = if estimate {
+        session.estimate(call)
+    } else {
+        session.query(call)
+    };
+    let output = result.result.ok()?;
+    Result::<bool, ()>::decode(&mut &output.data[..]).ok()?.ok()
+}
+
+#[test]
+fn dry_runs_do_not_consume_faults() {
+    let mut session = Session::<PinkRuntime>::new().expect("Failed to create session");
+    session.inject_fault(FaultInjection::new(
+        ExtFunction::CodeExists,
+        Fault::Error("Injected".into()),
+    ));
+
+    assert_eq!(code_exists(&mut session, true), None);
+    assert_eq!(code_exists(&mut session, false), None);
+    assert_eq!(code_exists(&mut session, false), Some(false));
+}
+
+#[test]
+fn faults_hit_after_skipped_calls() {
+    let mut session = Session::<PinkRuntime>::new().expect("Failed to create session");
+    session.inject_fault(
+        FaultInjection::new(ExtFunction::CodeExists, Fault::Error("Injected".into()))
+            .after(1)
+            .times(2),
+    );
+
+    assert_eq!(code_exists(&mut session, false), Some(false));
+    assert_eq!(code_exists(&mut session, false), None);
+    assert_eq!(code_exists(&mut session, false), None);
+    assert_eq!(code_exists(&mut session, false), Some(false));
+}
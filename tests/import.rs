@@ -0,0 +1,67 @@
+use drink::session::Session;
+use pink_drink::prelude::*;
+use pink_drink::{ContractDump, PinkRuntime};
+
+mod common;
+use common::{AccountId, Raw};
+
+/// Export a contract of `session` in the dump format, with its storage as raw keys.
+fn export(session: &mut Session<PinkRuntime>, contract: &AccountId, salt: &[u8]) -> String {
+    let storage: Vec<_> = session
+        .storage_keys(contract)
+        .expect("Contract not found")
+        .into_iter()
+        .map(|key| {
+            let value = session
+                .read_storage(contract, &key)
+                .expect("Contract not found")
+                .expect("Storage entry not found");
+            [format!("0x{}", hex::encode(key)), hex::encode(value)]
+        })
+        .collect();
+    let deployer: [u8; 32] = session.actor().into();
+    serde_json::json!({
+        "deployer": format!("0x{}", hex::encode(deployer)),
+        "salt": hex::encode(salt),
+        "code": hex::encode(common::wasm()),
+        "storage": storage,
+    })
+    .to_string()
+}
+
+#[test]
+fn imported_contracts_keep_their_address_and_storage() {
+    let mut session = Session::<PinkRuntime>::new().expect("Failed to create session");
+    let contract = common::deploy_with_salt(&mut session, b"imported");
+    common::message::<_, ()>(&contract, common::STORE, Raw(b"hello".to_vec()))
+        .submit_tx(&mut session)
+        .expect("Failed to store value");
+    let dump = ContractDump::from_json(&export(&mut session, &contract, b"imported"))
+        .expect("Failed to parse dump");
+
+    let mut target = Session::<PinkRuntime>::new().expect("Failed to create session");
+    let imported = target.import_contract(&dump).expect("Failed to import");
+    assert_eq!(imported, contract);
+    assert_eq!(
+        target.storage_keys(&imported).expect("Contract not found"),
+        vec![vec![0u8; 4]]
+    );
+    assert_eq!(
+        target
+            .read_storage(&imported, &[0; 4])
+            .expect("Contract not found"),
+        Some(b"hello".to_vec())
+    );
+
+    // The imported code runs, not the stub which wrote the storage.
+    common::message::<_, ()>(&imported, common::STORE, Raw(b"again".to_vec()))
+        .submit_tx(&mut target)
+        .expect("Failed to store value");
+    assert_eq!(
+        target
+            .read_storage(&imported, &[0; 4])
+            .expect("Contract not found"),
+        Some(b"again".to_vec())
+    );
+    assert!(target.import_contract(&dump).is_err());
+}
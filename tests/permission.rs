@@ -0,0 +1,71 @@
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use drink::{runtime::Runtime, session::Session};
+use pink_drink::prelude::*;
+use pink_drink::{accounts, PinkRuntime, Role};
+
+#[test]
+fn non_owner_cannot_set_driver() {
+    let mut session = Session::<PinkRuntime>::new().expect("Failed to create session");
+    let bob = accounts::bob().account_id();
+
+    let err = session
+        .as_actor(bob.clone(), |session| session.set_driver("Test", &bob))
+        .expect_err("Non-owner set a driver");
+    assert!(err.to_string().contains("BadOrigin"), "{err}");
+
+    let denials = session.permission_denials();
+    assert_eq!(denials.len(), 1);
+    assert_eq!(denials[0].caller, bob);
+    assert_eq!(denials[0].required, Role::ClusterOwner);
+    assert!(session
+        .debug_messages()
+        .iter()
+        .all(|message| !message.contains("BadOrigin")));
+}
+
+#[test]
+fn runtime_rejects_non_owner_prices() {
+    let mut session = Session::<PinkRuntime>::new().expect("Failed to create session");
+    let owner = session.cluster_owner().expect("No cluster owner");
+    let bob = accounts::bob().account_id();
+    assert_eq!(owner, PinkRuntime::default_actor());
+
+    let denied = session.query(|| PinkRuntime::set_cluster_prices(&bob, 1, 2, 3));
+    assert!(denied.is_err());
+    let allowed = session.query(|| PinkRuntime::set_cluster_prices(&owner, 1, 2, 3));
+    assert!(allowed.is_ok());
+}
+
+#[test]
+fn non_owner_cannot_set_prices() {
+    let mut session = Session::<PinkRuntime>::new().expect("Failed to create session");
+    let bob = accounts::bob().account_id();
+
+    let err = session
+        .as_actor(bob.clone(), |session| session.set_cluster_prices(1, 2, 3))
+        .expect_err("Non-owner set the cluster prices");
+    assert!(err.to_string().contains("BadOrigin"), "{err}");
+    let denials = session.take_permission_denials();
+    assert_eq!(denials.len(), 1);
+    assert_eq!(denials[0].caller, bob);
+    assert_eq!(denials[0].required, Role::ClusterOwner);
+
+    session
+        .as_cluster_owner(|session| session.set_cluster_prices(1, 2, 3))
+        .expect("No cluster owner")
+        .expect("Owner failed to set the cluster prices");
+    assert!(session.permission_denials().is_empty());
+}
+
+#[test]
+fn actor_is_restored_after_a_panic() {
+    let mut session = Session::<PinkRuntime>::new().expect("Failed to create session");
+    let actor = session.actor();
+
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        session.as_actor(accounts::bob().account_id(), |_| panic!("boom"))
+    }));
+    assert!(result.is_err());
+    assert_eq!(session.actor(), actor);
+}
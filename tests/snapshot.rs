@@ -0,0 +1,109 @@
+use drink::session::Session;
+use pink::chain_extension::StorageQuotaExceeded;
+use pink_drink::prelude::*;
+use pink_drink::{ClusterId, ExtFunction, PinkRuntime, Snapshot};
+
+mod common;
+use common::{AccountId, Raw};
+
+const ROOT_KEY: [u8; 4] = [0; 4];
+
+/// A session with a contract holding `b"before"` in its storage and its worker cache, a frozen
+/// off-chain clock and an idle cluster.
+fn prepared_session() -> (Session<PinkRuntime>, AccountId) {
+    let mut session = Session::<PinkRuntime>::new().expect("Failed to create session");
+    let contract = common::deploy(&mut session);
+    set_value(&mut session, &contract, b"before");
+    session.set_offchain_clock(Some(42));
+    session
+        .create_cluster(ClusterId::repeat_byte(1))
+        .expect("Failed to create cluster");
+    (session, contract)
+}
+
+/// Store `value` in the contract storage and in the worker cache.
+fn set_value(session: &mut Session<PinkRuntime>, contract: &AccountId, value: &[u8]) {
+    common::message::<_, ()>(contract, common::STORE, Raw(value.to_vec()))
+        .submit_tx(session)
+        .expect("Failed to store value");
+    common::ext::<_, Result<(), StorageQuotaExceeded>>(
+        contract,
+        ExtFunction::CacheSet,
+        (b"key".to_vec(), value.to_vec()),
+    )
+    .submit_tx(session)
+    .expect("Failed to call cache_set")
+    .expect("Cache quota exceeded");
+}
+
+fn assert_prepared_state(session: &mut Session<PinkRuntime>, contract: &AccountId) {
+    assert_eq!(
+        session
+            .read_storage(contract, &ROOT_KEY)
+            .expect("Contract not found"),
+        Some(b"before".to_vec())
+    );
+    let cached: Option<Vec<u8>> = common::ext(contract, ExtFunction::CacheGet, b"key".to_vec())
+        .query(session)
+        .expect("Failed to call cache_get");
+    assert_eq!(cached, Some(b"before".to_vec()));
+    let clock: u64 = common::ext(contract, ExtFunction::UntrustedMillisSinceUnixEpoch, ())
+        .query(session)
+        .expect("Failed to read the clock");
+    assert_eq!(clock, 42);
+    assert_eq!(session.active_cluster(), ClusterId::zero());
+    session
+        .with_cluster(ClusterId::repeat_byte(1), |session| {
+            assert!(session.query(PinkRuntime::system_contract).is_some());
+        })
+        .expect("Idle cluster not restored");
+}
+
+#[test]
+fn snapshots_restore_chain_and_offchain_state() {
+    let (mut session, contract) = prepared_session();
+    let snapshot = session.snapshot();
+
+    set_value(&mut session, &contract, b"after");
+    session.set_offchain_clock(Some(7));
+    session.restore(&snapshot);
+    assert_prepared_state(&mut session, &contract);
+
+    let mut restored = snapshot.new_session().expect("Failed to restore snapshot");
+    assert_prepared_state(&mut restored, &contract);
+}
+
+#[test]
+fn snapshots_survive_a_round_trip_through_a_file() {
+    let (mut session, contract) = prepared_session();
+    let path = std::env::temp_dir().join(format!("pink-drink-{}.snapshot", std::process::id()));
+    session.save_state(&path).expect("Failed to save snapshot");
+
+    let mut restored = Session::<PinkRuntime>::new().expect("Failed to create session");
+    let loaded = restored.load_state(&path);
+    std::fs::remove_file(&path).expect("Failed to remove snapshot file");
+    loaded.expect("Failed to load snapshot");
+    assert_prepared_state(&mut restored, &contract);
+}
+
+#[test]
+fn snapshots_with_a_bad_magic_or_version_are_rejected() {
+    let (mut session, _) = prepared_session();
+    let bytes = session.snapshot().to_bytes();
+
+    let mut bad_magic = bytes.clone();
+    bad_magic[0] ^= 0xff;
+    let err = Snapshot::from_bytes(&bad_magic).expect_err("Bad magic accepted");
+    assert!(err.to_string().contains("bad magic"), "{err}");
+
+    let mut bad_version = bytes.clone();
+    bad_version[8..12].copy_from_slice(&u32::MAX.to_le_bytes());
+    let err = Snapshot::from_bytes(&bad_version).expect_err("Bad version accepted");
+    assert!(
+        err.to_string().contains("UnsupportedSnapshotVersion"),
+        "{err}"
+    );
+
+    assert!(Snapshot::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    assert!(Snapshot::from_bytes(&bytes).is_ok());
+}
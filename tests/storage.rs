@@ -0,0 +1,58 @@
+use drink::session::Session;
+use pink_drink::prelude::*;
+use pink_drink::{accounts, ContractMetadata, PinkRuntime};
+use scale::Encode;
+
+mod common;
+use common::Raw;
+
+/// The metadata of a contract with a packed `count: u32` and a `balances: Mapping<AccountId, u128>`.
+fn metadata() -> ContractMetadata {
+    ContractMetadata::from_json(include_str!("fixtures/counter.json"))
+        .expect("Failed to parse the metadata")
+}
+
+#[test]
+fn storage_fields_are_read_by_their_layout_key() {
+    let mut session = Session::<PinkRuntime>::new().expect("Failed to create session");
+    let contract = common::deploy(&mut session);
+    common::message::<_, ()>(&contract, common::STORE, Raw(7u32.encode()))
+        .submit_tx(&mut session)
+        .expect("Failed to store the contract struct");
+
+    let metadata = metadata();
+    let root = metadata.key_of("").expect("No key for the contract struct");
+    assert_eq!(root, vec![0; 4]);
+    let count = session
+        .read_storage_as::<_, u32>(&contract, &root)
+        .expect("Failed to read the contract struct");
+    assert_eq!(count, Some(7));
+
+    let err = metadata
+        .key_of("count")
+        .expect_err("Packed fields have no key");
+    assert!(err.to_string().contains("PackedStorageField"));
+    let err = metadata
+        .key_of("missing")
+        .expect_err("Unknown fields have no key");
+    assert!(err.to_string().contains("UnknownStorageField"));
+}
+
+#[test]
+fn mapping_entries_are_written_by_their_layout_key() {
+    let mut session = Session::<PinkRuntime>::new().expect("Failed to create session");
+    let contract = common::deploy(&mut session);
+    let bob = accounts::bob().account_id();
+
+    let key = metadata()
+        .mapping_key("balances", &bob)
+        .expect("No key for the balances mapping");
+    assert_eq!(key, [vec![0x0d, 0x0c, 0x0b, 0x0a], bob.encode()].concat());
+    session
+        .write_storage_as(&contract, &key, &100u128)
+        .expect("Failed to write the balance");
+    let balance = session
+        .read_storage_as::<_, u128>(&contract, &key)
+        .expect("Failed to read the balance");
+    assert_eq!(balance, Some(100));
+}
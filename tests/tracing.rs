@@ -0,0 +1,59 @@
+use drink::{runtime::Runtime, session::Session};
+use pink_drink::prelude::*;
+use pink_drink::{accounts, CallKind, PinkRuntime};
+use scale::Encode;
+
+mod common;
+use common::Raw;
+
+#[test]
+fn traces_the_outermost_call() {
+    let mut session = Session::<PinkRuntime>::new().expect("Failed to create session");
+    session.set_tracing(true);
+    session
+        .set_driver("Test", &accounts::alice().account_id())
+        .expect("Failed to set driver");
+
+    let traces = session.traces();
+    assert_eq!(traces.len(), 1);
+    let trace = &traces[0];
+    assert_eq!(trace.caller, PinkRuntime::default_actor());
+    assert_eq!(trace.value, 0);
+    assert!(trace.gas_consumed.is_some());
+    assert_eq!(trace.selector(), Some(0xaa1e2030u32.to_be_bytes()));
+    assert!(trace.output.is_some());
+}
+
+#[test]
+fn traces_nested_calls_with_their_value() {
+    let mut session = Session::<PinkRuntime>::new().expect("Failed to create session");
+    let outer = common::deploy_with_salt(&mut session, b"outer");
+    let inner = common::deploy_with_salt(&mut session, b"inner");
+    session.fund(outer.clone(), 1_000);
+    let balance = session.free_balance(inner.clone());
+    session.set_tracing(true);
+
+    common::nested::<_, ()>(&outer, &inner, 100, common::DEBUG, Raw(b"hi".to_vec()))
+        .submit_tx(&mut session)
+        .expect("Failed to call");
+
+    let traces = session.traces();
+    assert_eq!(traces.len(), 1);
+    let calls = traces[0].calls();
+    assert_eq!(calls.len(), 2);
+    let (outermost, nested) = (calls[0], calls[1]);
+    assert_eq!(outermost.caller, session.actor());
+    assert_eq!(outermost.callee, outer);
+    assert_eq!(outermost.value, 0);
+    assert!(outermost.gas_consumed.is_some());
+    assert_eq!(nested.kind, CallKind::Call);
+    assert_eq!(nested.caller, outer);
+    assert_eq!(nested.callee, inner);
+    assert_eq!(nested.value, 100);
+    assert_eq!(
+        nested.input,
+        (common::DEBUG.to_le_bytes(), Raw(b"hi".to_vec())).encode()
+    );
+    assert_eq!(nested.output, Some(vec![0]));
+    assert_eq!(session.free_balance(inner), balance + 100);
+}
@@ -0,0 +1,117 @@
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use drink::session::Session;
+use pink::chain_extension::{PinkExtBackend, StorageQuotaExceeded};
+use pink::types::sgx::{AttestationType, SgxQuote};
+use pink_chain_extension::mock_ext::MockExtension;
+use pink_drink::prelude::*;
+use pink_drink::{ExtFunction, PinkRuntime, Snapshot, WorkerIdentity};
+
+mod common;
+use common::AccountId;
+
+/// Store `value` in the cache of the active worker, which queries write to directly.
+fn prepare_worker(session: &mut Session<PinkRuntime>, contract: &AccountId, value: &[u8]) {
+    common::ext::<_, Result<(), StorageQuotaExceeded>>(
+        contract,
+        ExtFunction::CacheSet,
+        (b"key".to_vec(), value.to_vec()),
+    )
+    .query(session)
+    .expect("Failed to call cache_set")
+    .expect("Cache quota exceeded");
+}
+
+fn cached(session: &mut Session<PinkRuntime>, contract: &AccountId) -> Option<Vec<u8>> {
+    common::ext(contract, ExtFunction::CacheGet, b"key".to_vec())
+        .query(session)
+        .expect("Failed to call cache_get")
+}
+
+fn clock(session: &mut Session<PinkRuntime>, contract: &AccountId) -> u64 {
+    common::ext(contract, ExtFunction::UntrustedMillisSinceUnixEpoch, ())
+        .query(session)
+        .expect("Failed to read the clock")
+}
+
+fn sgx_quote(contract: &AccountId) -> impl Callable<Ret = Option<SgxQuote>> {
+    common::ext(contract, ExtFunction::WorkerSgxQuote, ())
+}
+
+#[test]
+fn queries_get_the_sgx_quote_of_the_worker_identity() {
+    let mut session = Session::<PinkRuntime>::new().expect("Failed to create session");
+    let contract = common::deploy(&mut session);
+    let mock_quote = MockExtension
+        .worker_sgx_quote()
+        .expect("Failed to get the mock quote");
+    assert_eq!(sgx_quote(&contract).query(&mut session), Ok(mock_quote));
+
+    let quote = SgxQuote {
+        attestation_type: AttestationType::Dcap,
+        quote: b"quote".to_vec(),
+    };
+    session.set_worker_identity(WorkerIdentity::from_seed([1; 32]).with_sgx_quote(quote.clone()));
+    assert_eq!(sgx_quote(&contract).query(&mut session), Ok(Some(quote)));
+    // Transactions run on every worker, which have different quotes.
+    assert_eq!(sgx_quote(&contract).submit_tx(&mut session), Ok(None));
+}
+
+#[test]
+fn with_worker_switches_back_after_a_panic() {
+    let mut session = Session::<PinkRuntime>::new().expect("Failed to create session");
+    let worker = session.add_worker(WorkerIdentity::from_seed([1; 32]));
+
+    let inner = session
+        .with_worker(worker, |session| session.active_worker())
+        .expect("Failed to select worker");
+    assert_eq!(inner, worker);
+    assert_eq!(session.active_worker(), 0);
+
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        session.with_worker(worker, |_| panic!("boom"))
+    }));
+    assert!(result.is_err());
+    assert_eq!(session.active_worker(), 0);
+    assert!(session.worker_identity().is_none());
+}
+
+#[test]
+fn snapshots_keep_every_worker() {
+    let mut session = Session::<PinkRuntime>::new().expect("Failed to create session");
+    let contract = common::deploy(&mut session);
+    let identity = WorkerIdentity::from_seed([1; 32]);
+    let worker = session.add_worker(identity.clone());
+    session.set_offchain_clock(Some(10));
+    prepare_worker(&mut session, &contract, b"zero");
+    session
+        .with_worker(worker, |session| {
+            session.set_offchain_clock(Some(20));
+            prepare_worker(session, &contract, b"one");
+        })
+        .expect("Failed to select worker");
+
+    let snapshot =
+        Snapshot::from_bytes(&session.snapshot().to_bytes()).expect("Failed to decode snapshot");
+    let mut restored = snapshot.new_session().expect("Failed to restore snapshot");
+    assert_eq!(restored.active_worker(), 0);
+    assert!(restored.worker_identity().is_none());
+    assert_eq!(clock(&mut restored, &contract), 10);
+    assert_eq!(cached(&mut restored, &contract), Some(b"zero".to_vec()));
+
+    restored
+        .with_worker(worker, |session| {
+            let restored_identity = session.worker_identity().expect("No worker identity");
+            assert_eq!(
+                restored_identity.ecdh_public_key(),
+                identity.ecdh_public_key()
+            );
+            assert_eq!(clock(session, &contract), 20);
+            assert_eq!(cached(session, &contract), Some(b"one".to_vec()));
+        })
+        .expect("Failed to select worker");
+    assert_eq!(
+        restored.add_worker(WorkerIdentity::from_seed([2; 32])),
+        worker + 1
+    );
+}